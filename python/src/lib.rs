@@ -577,7 +577,7 @@ pub fn unified_listener(devices: Vec<Bound<'_, Device>>) -> PyResult<UnifiedEven
 }
 
 #[pyfunction]
-pub fn maximize_fd_limit() -> PyResult<()> {
+pub fn maximize_fd_limit() -> PyResult<usize> {
     ::rustuya::maximize_fd_limit().map_err(|e| {
         pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to maximize FD limit: {}", e))
     })
@@ -646,108 +646,9 @@ fn rustuya(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ScannerIterator>()?;
 
     let cmd_type = PyDict::new(py);
-    cmd_type.set_item(
-        "ApConfig",
-        ::rustuya::protocol::CommandType::ApConfig as u32,
-    )?;
-    cmd_type.set_item("Active", ::rustuya::protocol::CommandType::Active as u32)?;
-    cmd_type.set_item(
-        "SessKeyNegStart",
-        ::rustuya::protocol::CommandType::SessKeyNegStart as u32,
-    )?;
-    cmd_type.set_item(
-        "SessKeyNegResp",
-        ::rustuya::protocol::CommandType::SessKeyNegResp as u32,
-    )?;
-    cmd_type.set_item(
-        "SessKeyNegFinish",
-        ::rustuya::protocol::CommandType::SessKeyNegFinish as u32,
-    )?;
-    cmd_type.set_item("Unbind", ::rustuya::protocol::CommandType::Unbind as u32)?;
-    cmd_type.set_item("Control", ::rustuya::protocol::CommandType::Control as u32)?;
-    cmd_type.set_item("Status", ::rustuya::protocol::CommandType::Status as u32)?;
-    cmd_type.set_item(
-        "HeartBeat",
-        ::rustuya::protocol::CommandType::HeartBeat as u32,
-    )?;
-    cmd_type.set_item("DpQuery", ::rustuya::protocol::CommandType::DpQuery as u32)?;
-    cmd_type.set_item(
-        "QueryWifi",
-        ::rustuya::protocol::CommandType::QueryWifi as u32,
-    )?;
-    cmd_type.set_item(
-        "TokenBind",
-        ::rustuya::protocol::CommandType::TokenBind as u32,
-    )?;
-    cmd_type.set_item(
-        "ControlNew",
-        ::rustuya::protocol::CommandType::ControlNew as u32,
-    )?;
-    cmd_type.set_item(
-        "EnableWifi",
-        ::rustuya::protocol::CommandType::EnableWifi as u32,
-    )?;
-    cmd_type.set_item(
-        "WifiInfo",
-        ::rustuya::protocol::CommandType::WifiInfo as u32,
-    )?;
-    cmd_type.set_item(
-        "DpQueryNew",
-        ::rustuya::protocol::CommandType::DpQueryNew as u32,
-    )?;
-    cmd_type.set_item(
-        "SceneExecute",
-        ::rustuya::protocol::CommandType::SceneExecute as u32,
-    )?;
-    cmd_type.set_item(
-        "UpdateDps",
-        ::rustuya::protocol::CommandType::UpdateDps as u32,
-    )?;
-    cmd_type.set_item("UdpNew", ::rustuya::protocol::CommandType::UdpNew as u32)?;
-    cmd_type.set_item(
-        "ApConfigNew",
-        ::rustuya::protocol::CommandType::ApConfigNew as u32,
-    )?;
-    cmd_type.set_item(
-        "LanGwActive",
-        ::rustuya::protocol::CommandType::LanGwActive as u32,
-    )?;
-    cmd_type.set_item(
-        "LanSubDevRequest",
-        ::rustuya::protocol::CommandType::LanSubDevRequest as u32,
-    )?;
-    cmd_type.set_item(
-        "LanDeleteSubDev",
-        ::rustuya::protocol::CommandType::LanDeleteSubDev as u32,
-    )?;
-    cmd_type.set_item(
-        "LanReportSubDev",
-        ::rustuya::protocol::CommandType::LanReportSubDev as u32,
-    )?;
-    cmd_type.set_item(
-        "LanScene",
-        ::rustuya::protocol::CommandType::LanScene as u32,
-    )?;
-    cmd_type.set_item(
-        "LanPublishCloudConfig",
-        ::rustuya::protocol::CommandType::LanPublishCloudConfig as u32,
-    )?;
-    cmd_type.set_item(
-        "LanExportAppConfig",
-        ::rustuya::protocol::CommandType::LanExportAppConfig as u32,
-    )?;
-    cmd_type.set_item(
-        "LanPublishAppConfig",
-        ::rustuya::protocol::CommandType::LanPublishAppConfig as u32,
-    )?;
-    cmd_type.set_item(
-        "ReqDevInfo",
-        ::rustuya::protocol::CommandType::ReqDevInfo as u32,
-    )?;
-    cmd_type.set_item(
-        "LanExtStream",
-        ::rustuya::protocol::CommandType::LanExtStream as u32,
-    )?;
+    for cmd in ::rustuya::protocol::CommandType::all() {
+        cmd_type.set_item(cmd.name(), *cmd as u32)?;
+    }
     m.add("CommandType", cmd_type)?;
 
     Ok(())
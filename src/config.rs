@@ -0,0 +1,170 @@
+//! Device inventory loading.
+//!
+//! Describes devices in a JSON file instead of code, for setting up many devices at once.
+
+use crate::device::{Device, DeviceBuilder};
+use crate::error::{Result, TuyaError};
+use crate::protocol::{DeviceType, Version};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Connection settings for a single device, as stored in an inventory file.
+///
+/// Only `id` and `local_key` are required; everything else falls back to the
+/// `DeviceBuilder` defaults (auto-discovery, auto version, persistent connection).
+///
+/// ```
+/// use rustuya::config::DeviceConfig;
+///
+/// let configs: Vec<DeviceConfig> = serde_json::from_str(r#"[
+///     {"id": "bf0123456789abcdef", "local_key": "0123456789abcdef", "version": "3.4"},
+///     {"id": "bf9876543210fedcba", "local_key": "fedcba9876543210", "address": "192.168.1.50"}
+/// ]"#).unwrap();
+/// assert_eq!(configs[0].version, Some(rustuya::Version::V3_4));
+/// assert_eq!(configs[1].address.as_deref(), Some("192.168.1.50"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Device ID
+    pub id: String,
+    /// Local key used to encrypt traffic
+    pub local_key: String,
+    /// IP address (default: auto-discovery)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Protocol version, e.g. "3.3" (default: auto)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// Device type: "auto", "default", or "device22" (default: auto)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dev_type: Option<String>,
+    /// Keep the connection alive (default: true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persist: Option<bool>,
+    /// Human-readable device name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// DP definitions keyed by DP ID, e.g. `{"1": {"code": "switch_1", "type": "Boolean"}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapping: Option<Map<String, Value>>,
+}
+
+impl DeviceConfig {
+    /// Returns a `DeviceBuilder` preconfigured with these settings.
+    #[must_use]
+    pub fn builder(&self) -> DeviceBuilder {
+        let mut builder = DeviceBuilder::new(self.id.clone(), self.local_key.clone());
+        if let Some(address) = &self.address {
+            builder = builder.address(address.clone());
+        }
+        if let Some(version) = self.version {
+            builder = builder.version(version);
+        }
+        if let Some(dev_type) = &self.dev_type {
+            builder = builder.dev_type(DeviceType::from(dev_type.as_str()));
+        }
+        if let Some(persist) = self.persist {
+            builder = builder.persist(persist);
+        }
+        builder
+    }
+
+    /// Creates the device and starts its connection task.
+    #[must_use]
+    pub fn run(&self) -> Device {
+        self.builder().run()
+    }
+}
+
+/// Reads a JSON array of [`DeviceConfig`] entries from a file.
+pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<DeviceConfig>> {
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Imports a TinyTuya `devices.json` or `snapshot.json` file.
+///
+/// Reads `id`, `key`, `ip`, `ver`/`version`, `name`, and `mapping` from each entry. Both a
+/// top-level array and the snapshot's `{"devices": [...]}` form are accepted. Gateway
+/// sub-devices (`sub: true`) are skipped, since they are reached through their gateway
+/// with `Device::sub` rather than connected to directly.
+///
+/// ```no_run
+/// let configs = rustuya::config::import_tinytuya("devices.json").unwrap();
+/// let devices = rustuya::config::run_all(&configs);
+/// # drop(devices);
+/// ```
+pub fn import_tinytuya<P: AsRef<Path>>(path: P) -> Result<Vec<DeviceConfig>> {
+    parse_tinytuya(&std::fs::read_to_string(path)?)
+}
+
+/// Parses the contents of a TinyTuya `devices.json` or `snapshot.json`, in the format
+/// described on [`import_tinytuya`].
+///
+/// ```
+/// use rustuya::config::parse_tinytuya;
+///
+/// let configs = parse_tinytuya(r#"[{
+///     "name": "Desk Lamp", "id": "bf0123456789abcdef", "key": "0123456789abcdef",
+///     "ip": "192.168.1.50", "ver": "3.3",
+///     "mapping": {"20": {"code": "switch_led", "type": "Boolean"}}
+/// }]"#).unwrap();
+/// assert_eq!(configs[0].name.as_deref(), Some("Desk Lamp"));
+/// assert_eq!(configs[0].version, Some(rustuya::Version::V3_3));
+/// ```
+pub fn parse_tinytuya(data: &str) -> Result<Vec<DeviceConfig>> {
+    let root: Value = serde_json::from_str(data)?;
+    let entries = match &root {
+        Value::Array(list) => list,
+        Value::Object(obj) => obj
+            .get("devices")
+            .and_then(Value::as_array)
+            .ok_or_else(|| TuyaError::Json("Missing 'devices' list".into()))?,
+        _ => return Err(TuyaError::Json("Expected a list of devices".into())),
+    };
+
+    let text = |entry: &Value, key: &str| {
+        entry
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .map(ToString::to_string)
+    };
+
+    Ok(entries
+        .iter()
+        .filter(|entry| !entry.get("sub").and_then(Value::as_bool).unwrap_or(false))
+        .filter_map(|entry| {
+            let version = entry
+                .get("ver")
+                .or_else(|| entry.get("version"))
+                .and_then(|v| {
+                    match v {
+                        Value::Number(n) => n.as_f64().map(|f| format!("{f:.1}")),
+                        Value::String(s) => Some(s.clone()),
+                        _ => None,
+                    }
+                    .and_then(|s| s.parse::<Version>().ok())
+                    .filter(|v| *v != Version::Auto)
+                });
+
+            Some(DeviceConfig {
+                id: text(entry, "id")?,
+                local_key: text(entry, "key").unwrap_or_default(),
+                address: text(entry, "ip"),
+                version,
+                dev_type: None,
+                persist: None,
+                name: text(entry, "name"),
+                mapping: entry.get("mapping").and_then(Value::as_object).cloned(),
+            })
+        })
+        .collect())
+}
+
+/// Creates and starts a device for every entry in the list.
+#[must_use]
+pub fn run_all(configs: &[DeviceConfig]) -> Vec<Device> {
+    configs.iter().map(DeviceConfig::run).collect()
+}
@@ -0,0 +1,217 @@
+//! Device abstraction for generic and testable application code.
+//!
+//! [`DeviceController`] covers the everyday operations of a [`Device`], so code written
+//! against the trait can be handed a real device in production and a stand-in in tests.
+//! [`MockDevice`] is such a stand-in.
+
+use crate::device::Device;
+use crate::error::Result;
+use crate::protocol::TuyaMessage;
+use futures_core::stream::Stream;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+pub use async_trait::async_trait;
+
+/// Boxed stream of device messages, as returned by [`DeviceController::stream`].
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<TuyaMessage>> + Send>>;
+
+/// Common operations on a Tuya device.
+///
+/// Implemented by [`Device`] and [`MockDevice`]. The trait is object safe, so `Box<dyn DeviceController>`
+/// works as well as `impl DeviceController`.
+///
+/// ```
+/// use rustuya::controller::{DeviceController, MessageStream, async_trait};
+/// use rustuya::error::Result;
+/// use serde_json::{Value, json};
+/// use std::sync::Mutex;
+///
+/// // Application code only depends on the trait.
+/// async fn turn_on(device: &impl DeviceController) -> Result<()> {
+///     device.set_value("1", json!(true)).await?;
+///     Ok(())
+/// }
+///
+/// // A test double that records what it was asked to do.
+/// #[derive(Default)]
+/// struct Recorder(Mutex<Vec<Value>>);
+///
+/// #[async_trait]
+/// impl DeviceController for Recorder {
+///     fn id(&self) -> &str {
+///         "recorder"
+///     }
+///     async fn status(&self) -> Result<Option<String>> {
+///         Ok(Some(r#"{"dps":{"1":false}}"#.into()))
+///     }
+///     async fn set_dps(&self, dps: Value) -> Result<Option<String>> {
+///         self.0.lock().unwrap().push(dps);
+///         Ok(None)
+///     }
+///     fn stream(&self) -> MessageStream {
+///         Box::pin(futures_util::stream::empty())
+///     }
+/// }
+///
+/// let recorder = Recorder::default();
+/// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// rt.block_on(turn_on(&recorder)).unwrap();
+/// assert_eq!(recorder.0.lock().unwrap()[0], json!({"1": true}));
+/// ```
+#[async_trait]
+pub trait DeviceController: Send + Sync {
+    /// Device ID.
+    fn id(&self) -> &str;
+
+    /// Requests the current status (DPS values).
+    async fn status(&self) -> Result<Option<String>>;
+
+    /// Sets multiple DP values at once, e.g. `{"1": true, "2": 50}`.
+    async fn set_dps(&self, dps: Value) -> Result<Option<String>>;
+
+    /// Sets a single DP value. Defaults to `set_dps` with a one-entry object.
+    async fn set_value(&self, dp_id: &str, value: Value) -> Result<Option<String>> {
+        self.set_dps(serde_json::json!({ dp_id: value })).await
+    }
+
+    /// Returns a stream of messages and events from the device.
+    fn stream(&self) -> MessageStream;
+}
+
+#[async_trait]
+impl DeviceController for Device {
+    fn id(&self) -> &str {
+        Device::id(self)
+    }
+
+    async fn status(&self) -> Result<Option<String>> {
+        Device::status(self).await
+    }
+
+    async fn set_dps(&self, dps: Value) -> Result<Option<String>> {
+        Device::set_dps(self, dps).await
+    }
+
+    async fn set_value(&self, dp_id: &str, value: Value) -> Result<Option<String>> {
+        Device::set_value(self, dp_id, value).await
+    }
+
+    fn stream(&self) -> MessageStream {
+        Box::pin(self.listener())
+    }
+}
+
+/// In-memory [`DeviceController`] for tests of code written against the trait.
+///
+/// Records every `set_dps` call (including those made through `set_value`), answers
+/// `status()` with a configurable reply, and delivers messages passed to
+/// [`push_message`](Self::push_message) to every stream opened before the push. Clones
+/// share the same state.
+///
+/// ```
+/// use futures_util::StreamExt;
+/// use rustuya::controller::{DeviceController, MockDevice};
+/// use rustuya::error::Result;
+/// use rustuya::protocol::TuyaMessage;
+/// use serde_json::json;
+///
+/// // Turns DP 1 on if the device reports it off.
+/// async fn ensure_on(device: &impl DeviceController) -> Result<()> {
+///     let status = device.status().await?.unwrap_or_default();
+///     if status.contains(r#""1":false"#) {
+///         device.set_value("1", json!(true)).await?;
+///     }
+///     Ok(())
+/// }
+///
+/// let mock = MockDevice::new("bf0123456789abcdef");
+/// mock.set_status_reply(Ok(Some(r#"{"dps":{"1":false}}"#.into())));
+///
+/// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// rt.block_on(async {
+///     ensure_on(&mock).await.unwrap();
+///     assert_eq!(mock.set_dps_calls(), vec![json!({"1": true})]);
+///
+///     // Messages pushed into the mock show up on its streams.
+///     let mut events = mock.stream();
+///     mock.push_message(TuyaMessage { payload: br#"{"dps":{"1":true}}"#.to_vec(), ..Default::default() });
+///     let msg = events.next().await.unwrap().unwrap();
+///     assert_eq!(msg.payload, br#"{"dps":{"1":true}}"#);
+/// });
+/// ```
+#[derive(Clone)]
+pub struct MockDevice {
+    id: String,
+    inner: Arc<MockState>,
+}
+
+struct MockState {
+    status_reply: Mutex<Result<Option<String>>>,
+    set_dps_calls: Mutex<Vec<Value>>,
+    messages: broadcast::Sender<TuyaMessage>,
+}
+
+impl MockDevice {
+    /// Creates a mock whose `status()` answers `Ok(None)` until a reply is set.
+    pub fn new<I: Into<String>>(id: I) -> Self {
+        let (messages, _) = broadcast::channel(128);
+        Self {
+            id: id.into(),
+            inner: Arc::new(MockState {
+                status_reply: Mutex::new(Ok(None)),
+                set_dps_calls: Mutex::new(Vec::new()),
+                messages,
+            }),
+        }
+    }
+
+    /// Sets what every following `status()` call returns.
+    pub fn set_status_reply(&self, reply: Result<Option<String>>) {
+        *self.inner.status_reply.lock() = reply;
+    }
+
+    /// Returns the DPS objects passed to `set_dps`, oldest first.
+    #[must_use]
+    pub fn set_dps_calls(&self) -> Vec<Value> {
+        self.inner.set_dps_calls.lock().clone()
+    }
+
+    /// Delivers `msg` to every open `stream()`. Streams opened later do not see it.
+    pub fn push_message(&self, msg: TuyaMessage) {
+        let _ = self.inner.messages.send(msg);
+    }
+}
+
+#[async_trait]
+impl DeviceController for MockDevice {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn status(&self) -> Result<Option<String>> {
+        self.inner.status_reply.lock().clone()
+    }
+
+    async fn set_dps(&self, dps: Value) -> Result<Option<String>> {
+        self.inner.set_dps_calls.lock().push(dps);
+        Ok(None)
+    }
+
+    fn stream(&self) -> MessageStream {
+        let mut rx = self.inner.messages.subscribe();
+        Box::pin(async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => yield Ok(msg),
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
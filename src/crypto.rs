@@ -1,14 +1,17 @@
 //! Cryptographic operations for the Tuya protocol.
 //!
-//! Handles AES-128-ECB (v3.1, v3.3) and AES-128-GCM (v3.4, v3.5) encryption and decryption.
+//! Handles AES-128-ECB (v3.1, v3.3) and AES-128-GCM (v3.4, v3.5) encryption and decryption,
+//! plus AES-128-CBC for the few Tuya-derived devices that use it.
 
 use crate::error::{Result, TuyaError};
+use crate::protocol::{DeviceType, Version, get_protocol};
 use aes::Aes128;
 use aes_gcm::{
     Aes128Gcm, Nonce,
     aead::{Aead, KeyInit, Payload},
 };
-use cipher::{BlockDecryptMut, BlockEncryptMut};
+use cbc::{Decryptor as CbcDecryptor, Encryptor as CbcEncryptor};
+use cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use ecb::{Decryptor, Encryptor};
 
 pub struct TuyaCipher {
@@ -59,25 +62,7 @@ impl TuyaCipher {
             result
         } else {
             let mut encryptor = Encryptor::<Aes128>::new(&self.key.into());
-
-            let padded_data = if padding {
-                let len = data.len();
-                let remainder = len % 16;
-                let padding_len = 16 - remainder;
-
-                let mut p = data.to_vec();
-                for _ in 0..padding_len {
-                    p.push(padding_len as u8);
-                }
-                p
-            } else {
-                if !data.len().is_multiple_of(16) {
-                    return Err(TuyaError::EncryptionFailed);
-                }
-                data.to_vec()
-            };
-
-            let mut ciphertext = padded_data.clone();
+            let mut ciphertext = pad_block(data, padding, TuyaError::EncryptionFailed)?;
             for chunk in ciphertext.chunks_mut(16) {
                 let block = cipher::generic_array::GenericArray::from_mut_slice(chunk);
                 encryptor.encrypt_block_mut(block);
@@ -86,13 +71,72 @@ impl TuyaCipher {
             ciphertext
         };
 
-        if use_base64 {
-            use base64::{Engine as _, engine::general_purpose};
-            let b64_str = general_purpose::STANDARD.encode(&encrypted_bytes);
-            Ok(b64_str.into_bytes())
-        } else {
-            Ok(encrypted_bytes)
+        Ok(encode_output(encrypted_bytes, use_base64))
+    }
+
+    /// Encrypts with AES-128-CBC using the given 16-byte IV.
+    ///
+    /// Kept separate from [`encrypt`](Self::encrypt), where an IV always selects GCM. The IV
+    /// is not prepended to the output.
+    ///
+    /// ```
+    /// use rustuya::crypto::TuyaCipher;
+    ///
+    /// // NIST SP 800-38A, F.2.1 (first block)
+    /// let key = hex::decode("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+    /// let iv = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+    /// let plain = hex::decode("6bc1bee22e409f96e93d7e117393172a").unwrap();
+    ///
+    /// let cipher = TuyaCipher::new(&key).unwrap();
+    /// let encrypted = cipher.encrypt_cbc(&plain, &iv, false, false).unwrap();
+    /// assert_eq!(hex::encode(&encrypted), "7649abac8119b246cee98e9b12e9197d");
+    ///
+    /// let padded = cipher.encrypt_cbc(br#"{"dps":{"1":true}}"#, &iv, true, true).unwrap();
+    /// let decrypted = cipher.decrypt_cbc(&padded, &iv, true, true).unwrap();
+    /// assert_eq!(decrypted, br#"{"dps":{"1":true}}"#);
+    /// ```
+    pub fn encrypt_cbc(
+        &self,
+        data: &[u8],
+        iv: &[u8],
+        use_base64: bool,
+        padding: bool,
+    ) -> Result<Vec<u8>> {
+        let mut encryptor = CbcEncryptor::<Aes128>::new_from_slices(&self.key, iv)
+            .map_err(|_| TuyaError::EncryptionFailed)?;
+        let mut ciphertext = pad_block(data, padding, TuyaError::EncryptionFailed)?;
+        for chunk in ciphertext.chunks_mut(16) {
+            let block = cipher::generic_array::GenericArray::from_mut_slice(chunk);
+            encryptor.encrypt_block_mut(block);
         }
+
+        Ok(encode_output(ciphertext, use_base64))
+    }
+
+    /// Decrypts AES-128-CBC data using the given 16-byte IV. See [`encrypt_cbc`](Self::encrypt_cbc).
+    pub fn decrypt_cbc(
+        &self,
+        data: &[u8],
+        iv: &[u8],
+        use_base64: bool,
+        padding: bool,
+    ) -> Result<Vec<u8>> {
+        let mut decryptor = CbcDecryptor::<Aes128>::new_from_slices(&self.key, iv)
+            .map_err(|_| TuyaError::DecryptionFailed)?;
+        let mut plaintext = decode_input(data, use_base64)?;
+        if !plaintext.len().is_multiple_of(16) {
+            return Err(TuyaError::DecryptionFailed);
+        }
+
+        for chunk in plaintext.chunks_mut(16) {
+            let block = cipher::generic_array::GenericArray::from_mut_slice(chunk);
+            decryptor.decrypt_block_mut(block);
+        }
+
+        if padding {
+            unpad_block(&mut plaintext)?;
+        }
+        Ok(plaintext)
     }
 
     pub fn decrypt(
@@ -103,14 +147,7 @@ impl TuyaCipher {
         header: Option<&[u8]>,
         _tag: Option<&[u8]>,
     ) -> Result<Vec<u8>> {
-        let input_data = if use_base64 {
-            use base64::{Engine as _, engine::general_purpose};
-            general_purpose::STANDARD
-                .decode(data)
-                .map_err(|_| TuyaError::DecryptionFailed)?
-        } else {
-            data.to_vec()
-        };
+        let input_data = decode_input(data, use_base64)?;
 
         if let Some(iv_bytes) = iv {
             let nonce = Nonce::from_slice(&iv_bytes[..12]);
@@ -139,20 +176,105 @@ impl TuyaCipher {
                 decryptor.decrypt_block_mut(block);
             }
 
-            if plaintext.is_empty() {
-                return Ok(plaintext);
-            }
-            let pad_len = plaintext[plaintext.len() - 1] as usize;
-            if pad_len == 0 || pad_len > 16 || pad_len > plaintext.len() {
-                return Err(TuyaError::DecryptionFailed);
-            }
-            for i in 0..pad_len {
-                if plaintext[plaintext.len() - 1 - i] != pad_len as u8 {
-                    return Err(TuyaError::DecryptionFailed);
-                }
-            }
-            plaintext.truncate(plaintext.len() - pad_len);
+            unpad_block(&mut plaintext)?;
             Ok(plaintext)
         }
     }
 }
+
+/// Derives the v3.4/v3.5 session key from the local key and the two handshake nonces,
+/// exactly as a `Device` does after a successful negotiation.
+///
+/// The nonces are XORed and encrypted with the local key: AES-ECB for v3.4, AES-GCM with
+/// the first 12 bytes of `local_nonce` as IV for v3.5. The local nonce is the payload of
+/// the client's `SessKeyNegStart` frame, and the remote nonce the first 16 bytes of the
+/// device's `SessKeyNegResp` payload, so keys can be checked against captured traffic.
+/// Older versions do not negotiate a session key and fail with `FunctionNotSupported`.
+///
+/// ```
+/// use rustuya::Version;
+/// use rustuya::crypto::derive_session_key;
+///
+/// let local_nonce: Vec<u8> = (0..16).collect();
+/// let remote_nonce: Vec<u8> = (16..32).collect();
+///
+/// let key = derive_session_key(b"0123456789abcdef", &local_nonce, &remote_nonce, Version::V3_4)
+///     .unwrap();
+/// assert_eq!(hex::encode(key), "377222e061a924c591cd9c27ea163ed4");
+///
+/// let key = derive_session_key(b"0123456789abcdef", &local_nonce, &remote_nonce, Version::V3_5)
+///     .unwrap();
+/// assert_eq!(hex::encode(key), "ed2fd40d0e642b26f836170ad08ba03d");
+///
+/// assert!(derive_session_key(b"0123456789abcdef", &local_nonce, &remote_nonce, Version::V3_3)
+///     .is_err());
+/// ```
+pub fn derive_session_key(
+    local_key: &[u8],
+    local_nonce: &[u8],
+    remote_nonce: &[u8],
+    version: Version,
+) -> Result<Vec<u8>> {
+    if local_nonce.len() < 12 || remote_nonce.is_empty() {
+        return Err(TuyaError::InvalidPayload);
+    }
+    let protocol = get_protocol(version, DeviceType::Default);
+    if !protocol.requires_session_key() {
+        return Err(TuyaError::FunctionNotSupported);
+    }
+    let (session_key, _) = protocol.finalize_session_key(local_nonce, remote_nonce, local_key)?;
+    let cipher = TuyaCipher::new(local_key)?;
+    protocol.encrypt_session_key(&session_key, &cipher, local_nonce)
+}
+
+/// Applies PKCS#7 padding, or checks that `data` is already block-aligned.
+fn pad_block(data: &[u8], padding: bool, err: TuyaError) -> Result<Vec<u8>> {
+    if padding {
+        let padding_len = 16 - data.len() % 16;
+        let mut p = data.to_vec();
+        p.resize(data.len() + padding_len, padding_len as u8);
+        Ok(p)
+    } else if data.len().is_multiple_of(16) {
+        Ok(data.to_vec())
+    } else {
+        Err(err)
+    }
+}
+
+/// Strips PKCS#7 padding in place. Empty input is left as is.
+fn unpad_block(plaintext: &mut Vec<u8>) -> Result<()> {
+    if plaintext.is_empty() {
+        return Ok(());
+    }
+    let pad_len = plaintext[plaintext.len() - 1] as usize;
+    if pad_len == 0 || pad_len > 16 || pad_len > plaintext.len() {
+        return Err(TuyaError::DecryptionFailed);
+    }
+    for i in 0..pad_len {
+        if plaintext[plaintext.len() - 1 - i] != pad_len as u8 {
+            return Err(TuyaError::DecryptionFailed);
+        }
+    }
+    plaintext.truncate(plaintext.len() - pad_len);
+    Ok(())
+}
+
+fn encode_output(bytes: Vec<u8>, use_base64: bool) -> Vec<u8> {
+    if use_base64 {
+        use base64::{Engine as _, engine::general_purpose};
+        general_purpose::STANDARD.encode(&bytes).into_bytes()
+    } else {
+        bytes
+    }
+}
+
+fn decode_input(data: &[u8], use_base64: bool) -> Result<Vec<u8>> {
+    if use_base64 {
+        use base64::{Engine as _, engine::general_purpose};
+        general_purpose::STANDARD
+            .decode(data)
+            .map_err(|_| TuyaError::DecryptionFailed)
+    } else {
+        Ok(data.to_vec())
+    }
+}
@@ -4,42 +4,129 @@
 
 use crate::crypto::TuyaCipher;
 use crate::error::{
-    ERR_DEVTYPE, ERR_JSON, ERR_OFFLINE, ERR_PAYLOAD, ERR_SUCCESS, Result, TuyaError,
-    get_error_message,
+    ERR_AUTH_REJECTED, ERR_CONNECT, ERR_DEVTYPE, ERR_JSON, ERR_KEY_OR_VER, ERR_OFFLINE,
+    ERR_PAYLOAD, ERR_STATE, ERR_SUCCESS, Result, TuyaError, get_error_message,
 };
+use crate::protocol::v33::ProtocolV33;
 use crate::protocol::{
-    CommandType, DeviceType, PREFIX_55AA, PREFIX_6699, TuyaHeader, TuyaMessage, Version,
-    get_protocol, pack_message, parse_header, unpack_message,
+    CommandType, DeviceType, MAX_PREFIX_SCAN, PREFIX_55AA, PREFIX_6699, SUFFIX_55AA, SUFFIX_6699,
+    TuyaHeader, TuyaMessage, TuyaProtocol, Version, get_protocol, pack_message,
+    parse_header_with_limit, read_prefix, unpack_message, unpack_message_with_verify,
 };
-use crate::scanner::get as get_scanner;
+use crate::scanner::{DiscoveryResult, get as get_scanner};
 use futures_core::stream::Stream;
 use hex;
+#[cfg(not(feature = "tracing"))]
 use log::{debug, error, info, trace, warn};
 use parking_lot::RwLock;
 use rand::RngCore;
 use serde::Serialize;
 use serde_json::Value;
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf};
 use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::{Duration, sleep, timeout};
+use tokio_socks::tcp::Socks5Stream;
 use tokio_util::sync::CancellationToken;
+#[cfg(feature = "tracing")]
+use tracing::{debug, error, info, trace, warn};
+
+pub mod dp_types;
+pub use crate::energy;
 
 const SLEEP_HEARTBEAT_DEFAULT: Duration = Duration::from_secs(7);
 const SLEEP_HEARTBEAT_CHECK: Duration = Duration::from_secs(5);
 const SLEEP_RECONNECT_MIN: Duration = Duration::from_secs(16);
 const SLEEP_RECONNECT_MAX: Duration = Duration::from_secs(4096);
 const SLEEP_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(30);
+const SLEEP_SEQUENCE_NOWAIT: Duration = Duration::from_millis(200);
+/// Status reports arriving this soon after a command are treated as its confirmation.
+const STATUS_REPORT_ECHO_WINDOW: Duration = Duration::from_secs(2);
+/// Per-attempt wait and resends used by [`discover_all_sub_devices`], short enough that
+/// devices which are not gateways drop out quickly.
+const SUB_DISCOVER_ALL_TIMEOUT: Duration = Duration::from_secs(2);
+const SUB_DISCOVER_ALL_RETRIES: u32 = 1;
 
 const ADDR_AUTO: &str = "Auto";
+const PORT_DEFAULT: u16 = 6668;
 const DATA_UNVALID: &str = "data unvalid";
 
-const CHAN_BROADCAST_CAPACITY: usize = 128;
+const CHAN_BROADCAST_CAPACITY_DEFAULT: usize = 128;
 const CHAN_MPSC_CAPACITY: usize = 64;
 
+/// Source of the payload timestamp, in seconds since the Unix epoch.
+type Clock = Arc<dyn Fn() -> u64 + Send + Sync>;
+/// Called after each successful connection, see [`DeviceBuilder::on_connect`].
+type ConnectHook = Arc<dyn Fn(&Device) + Send + Sync>;
+/// Called when an established connection is lost, see [`DeviceBuilder::on_disconnect`].
+type DisconnectHook = Arc<dyn Fn(&Device, &TuyaError) + Send + Sync>;
+/// Sees every raw frame on the socket, see [`DeviceBuilder::wire_tap`].
+type WireTap = Arc<dyn Fn(Direction, &[u8]) + Send + Sync>;
+
+/// Every device created in this process, for [`Device::registry_snapshot`] and for
+/// sharing one connection per device ID. Holds weak references only, so it never keeps
+/// a device alive.
+static REGISTRY: parking_lot::Mutex<Vec<RegistryEntry>> = parking_lot::Mutex::new(Vec::new());
+
+struct RegistryEntry {
+    id: String,
+    state: Weak<RwLock<DeviceState>>,
+    tx: Option<mpsc::WeakSender<DeviceCommand>>,
+    broadcast_tx: tokio::sync::broadcast::WeakSender<TuyaMessage>,
+    outbound_tx: tokio::sync::broadcast::WeakSender<OutboundPacket>,
+    connection_tx: tokio::sync::broadcast::WeakSender<ConnectionEvent>,
+    report_tx: tokio::sync::broadcast::WeakSender<StatusReport>,
+    cancel_token: CancellationToken,
+    nowait: Arc<AtomicBool>,
+    shared: bool,
+}
+
+impl RegistryEntry {
+    fn new(device: &Device, shared: bool) -> Self {
+        Self {
+            id: device.id.clone(),
+            state: Arc::downgrade(&device.state),
+            tx: device.tx.as_ref().map(mpsc::Sender::downgrade),
+            broadcast_tx: device.broadcast_tx.downgrade(),
+            outbound_tx: device.outbound_tx.downgrade(),
+            connection_tx: device.connection_tx.downgrade(),
+            report_tx: device.report_tx.downgrade(),
+            cancel_token: device.cancel_token.clone(),
+            nowait: device.nowait.clone(),
+            shared,
+        }
+    }
+
+    /// Returns a new handle to the device if it is still running.
+    fn upgrade(&self) -> Option<Device> {
+        if self.cancel_token.is_cancelled() {
+            return None;
+        }
+        let tx = match &self.tx {
+            Some(tx) => Some(tx.upgrade()?),
+            None => None,
+        };
+        Some(Device {
+            id: self.id.clone(),
+            state: self.state.upgrade()?,
+            tx,
+            broadcast_tx: self.broadcast_tx.upgrade()?,
+            outbound_tx: self.outbound_tx.upgrade()?,
+            connection_tx: self.connection_tx.upgrade()?,
+            report_tx: self.report_tx.upgrade()?,
+            cancel_token: self.cancel_token.clone(),
+            nowait: self.nowait.clone(),
+        })
+    }
+}
+
 /// Commands that must return data (payload) and should not return on empty ACK.
 const MANDATORY_DATA_CMDS: &[u32] = &[CommandType::LanExtStream as u32];
 
@@ -78,6 +165,34 @@ impl SubDevice {
         self.request(CommandType::DpQuery, None).await
     }
 
+    /// Asks the gateway to re-report this sub-device's DPs (`UpdateDps` scoped to the CID).
+    pub async fn refresh(&self) -> Result<Option<String>> {
+        self.request(CommandType::UpdateDps, None).await
+    }
+
+    /// Returns this sub-device's current DPs as a JSON object.
+    ///
+    /// Sub-devices often ignore a bare status query, so if the first reply carries no DPs a
+    /// `refresh()` is sent and the query is retried once. Replies are matched on CID, so other
+    /// sub-devices reporting through the same gateway are ignored.
+    pub async fn status_now(&self) -> Result<Value> {
+        if let Some(dps) = self.status().await?.as_deref().and_then(extract_dps) {
+            return Ok(dps);
+        }
+        if let Ok(Some(dps)) = self
+            .refresh()
+            .await
+            .map(|r| r.as_deref().and_then(extract_dps))
+        {
+            return Ok(dps);
+        }
+        self.status()
+            .await?
+            .as_deref()
+            .and_then(extract_dps)
+            .ok_or(TuyaError::InvalidPayload)
+    }
+
     pub async fn set_dps(&self, dps: Value) -> Result<Option<String>> {
         self.request(CommandType::Control, Some(dps)).await
     }
@@ -96,9 +211,379 @@ impl SubDevice {
         }
     }
 
+    /// Sets several DPs in one command.
+    ///
+    /// On v3.4+ gateways the DPs are nested under `data` together with the sub-device's
+    /// `cid` and `ctype`; older versions put `cid` next to `dps` at the root.
+    ///
+    /// ```
+    /// use futures_util::StreamExt;
+    /// use rustuya::{Device, Version};
+    /// use serde_json::{Value, json};
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// rt.block_on(async {
+    ///     let payload_for = |version: Version| async move {
+    ///         let gateway = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///             .address("192.0.2.1")
+    ///             .version(version)
+    ///             .dry_run(true)
+    ///             .shared(false)
+    ///             .run();
+    ///         let mut packets = Box::pin(gateway.outbound_packets());
+    ///         let sub = gateway.sub("a4c1380000000001");
+    ///         sub.set_values(vec![(1, json!(true)), (2, json!(50))]).await.unwrap();
+    ///         let packet = packets.next().await.unwrap();
+    ///         serde_json::from_slice::<Value>(&packet.payload).unwrap()
+    ///     };
+    ///
+    ///     let v34 = payload_for(Version::V3_4).await;
+    ///     assert_eq!(v34["protocol"], 5);
+    ///     assert_eq!(v34["data"]["cid"], "a4c1380000000001");
+    ///     assert_eq!(v34["data"]["ctype"], 0);
+    ///     assert_eq!(v34["data"]["dps"], json!({"1": true, "2": 50}));
+    ///
+    ///     let v33 = payload_for(Version::V3_3).await;
+    ///     assert_eq!(v33["cid"], "a4c1380000000001");
+    ///     assert_eq!(v33["dps"], json!({"1": true, "2": 50}));
+    /// });
+    /// ```
+    pub async fn set_values(&self, values: Vec<(u32, Value)>) -> Result<Option<String>> {
+        let dps: serde_json::Map<String, Value> = values
+            .into_iter()
+            .map(|(dp, value)| (dp.to_string(), value))
+            .collect();
+        self.set_dps(Value::Object(dps)).await
+    }
+
     pub async fn request(&self, cmd: CommandType, data: Option<Value>) -> Result<Option<String>> {
         self.parent.request(cmd, data, Some(self.cid.clone())).await
     }
+
+    /// Sends a command and returns the gateway's reply for this sub-device, even in
+    /// nowait mode.
+    ///
+    /// The reply is the first message whose command matches (or is a status report) and
+    /// whose `cid`, at the payload root or under `data`, equals this sub-device's CID.
+    /// Sequence numbers are ignored since gateways do not echo them reliably. An empty
+    /// ACK and a device error reply (cmd 0) also count as the reply. Concurrent requests
+    /// for other sub-devices are never handed this reply. Fails with `Timeout` if nothing
+    /// matches within the parent device's `request_timeout()`.
+    pub async fn request_await(
+        &self,
+        cmd: CommandType,
+        data: Option<Value>,
+    ) -> Result<TuyaMessage> {
+        self.parent
+            .pipeline(vec![(cmd, data, Some(self.cid.clone()))])
+            .await
+            .pop()
+            .unwrap_or(Err(TuyaError::Offline))
+    }
+}
+
+/// SOCKS5 proxy used for the device TCP connection. See [`DeviceBuilder::proxy`].
+#[derive(Clone)]
+pub struct ProxyConfig {
+    /// Proxy host name or IP address.
+    pub host: String,
+    /// Proxy port.
+    pub port: u16,
+    /// Optional username/password authentication.
+    pub auth: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    /// Creates a SOCKS5 proxy configuration without authentication.
+    pub fn socks5<H: Into<String>>(host: H, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            auth: None,
+        }
+    }
+
+    /// Sets username/password authentication for the proxy.
+    #[must_use]
+    pub fn auth<U: Into<String>, P: Into<String>>(mut self, username: U, password: P) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+}
+
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.auth.as_ref().map(|(u, _)| u))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Snapshot of per-device connection counters, returned by [`Device::metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceMetrics {
+    /// Packets written to the device socket, including heartbeats and handshakes.
+    pub packets_sent: u64,
+    /// Packets read from the device socket.
+    pub packets_received: u64,
+    /// Bytes written to the device socket.
+    pub bytes_sent: u64,
+    /// Bytes read from the device socket.
+    pub bytes_received: u64,
+    /// Successful connections after the first one.
+    pub reconnects: u64,
+    /// Consecutive failed connection attempts.
+    pub failure_count: u32,
+    /// The most recent connection error, if any.
+    pub last_error: Option<String>,
+    /// The backoff currently being waited out before the next attempt, if any.
+    pub current_backoff: Option<Duration>,
+    /// How long the most recent successful session key negotiation took (v3.4/v3.5 only).
+    pub last_handshake: Option<Duration>,
+    /// Malformed frames dropped by resynchronizing on the next packet prefix.
+    pub resynced_frames: u64,
+}
+
+/// Read-only dump of a device's configuration and connection state for bug reports,
+/// returned by [`Device::diagnostics`]. Never contains the local or session key.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceDiagnostics {
+    /// Device ID.
+    pub id: String,
+    /// Address as configured, e.g. `"Auto"` or `"192.168.1.40"`.
+    pub config_address: String,
+    /// IP the connection actually uses, once known.
+    pub resolved_ip: Option<String>,
+    /// TCP port.
+    pub port: u16,
+    /// Protocol version currently in use.
+    pub version: Version,
+    /// Device type currently in use (`"auto"`, `"default"`, or `"device22"`).
+    pub dev_type: String,
+    /// Whether the device is connected right now.
+    pub connected: bool,
+    /// Whether the device has been stopped.
+    pub stopped: bool,
+    /// Whether the connection is kept alive in the background.
+    pub persist: bool,
+    /// Whether commands return without waiting for the reply.
+    pub nowait: bool,
+    /// Consecutive failed connection attempts.
+    pub failure_count: u32,
+    /// Time since the last packet was read from the device.
+    pub last_received: Duration,
+    /// Time since the last packet was written to the device.
+    pub last_sent: Duration,
+    /// Whether a v3.4/v3.5 session key is currently negotiated.
+    pub has_session_key: bool,
+    /// Traffic and connection counters, as returned by [`Device::metrics`].
+    pub metrics: DeviceMetrics,
+}
+
+/// A packet written to (or, in dry-run mode, withheld from) the device socket.
+/// Returned by [`Device::outbound_packets`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutboundPacket {
+    /// Sequence number of the packet.
+    pub seqno: u32,
+    /// Command code.
+    pub cmd: u32,
+    /// Payload before encryption (usually JSON).
+    pub payload: Vec<u8>,
+    /// Complete encoded packet, exactly as it goes on the wire.
+    pub packet: Vec<u8>,
+}
+
+/// Which way a frame passed to a [`DeviceBuilder::wire_tap`] callback travelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Written to the device.
+    Outbound,
+    /// Read from the device.
+    Inbound,
+}
+
+/// Progress of the background connection loop. Returned by [`Device::connection_events`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ConnectionEvent {
+    /// A connection attempt is starting.
+    Attempt {
+        /// Consecutive failed attempts plus one; back to 1 once a connection has been stable.
+        attempt: u32,
+    },
+    /// The previous attempt failed; the next one starts once `backoff` has elapsed.
+    Backoff {
+        /// Number of the attempt that follows the wait.
+        attempt: u32,
+        /// The error that ended the previous attempt or connection, if known.
+        error: Option<String>,
+        /// How long the connection loop waits before that attempt.
+        backoff: Duration,
+    },
+}
+
+/// A status report (`Status`, 0x08) the device pushed on its own, e.g. after a button press.
+/// Returned by [`Device::status_reports`]. Query replies arrive as `DpQuery` and are not
+/// included.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusReport {
+    /// The reported DPs.
+    pub dps: Value,
+    /// Child ID of the reporting gateway sub-device, if any.
+    pub cid: Option<String>,
+    /// Whether a command was sent to the device just before (within 2 seconds), so the
+    /// report most likely confirms that command rather than a physical interaction.
+    pub after_command: bool,
+}
+
+/// Online state of a gateway sub-device, as reported by `subdev_online_stat_query`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubDeviceStatus {
+    /// Child ID of the sub-device.
+    pub cid: String,
+    /// Whether the gateway reports the sub-device as online.
+    pub online: bool,
+    /// Network node ID, if the gateway includes it.
+    pub node_id: Option<String>,
+}
+
+/// Parses a sub-device online/offline report into a flat list.
+///
+/// Accepts both `{"data": {"online": [...], "offline": [...]}}` and the same lists at the
+/// root. Entries may be plain CID strings or objects with `cid` and `node_id`/`nodeId`.
+fn parse_sub_device_report(payload: &str) -> Result<Vec<SubDeviceStatus>> {
+    let val: Value = serde_json::from_str(payload)?;
+    if let Some(code) = val.get(keys::ERR_CODE).and_then(Value::as_u64) {
+        return Err(TuyaError::from_code(code as u32));
+    }
+
+    let data = val.get("data").unwrap_or(&val);
+    let mut result = Vec::new();
+    for (key, online) in [("online", true), ("offline", false)] {
+        let Some(entries) = data.get(key).and_then(Value::as_array) else {
+            continue;
+        };
+        for entry in entries {
+            let (cid, node_id) = match entry {
+                Value::String(cid) => (Some(cid.clone()), None),
+                Value::Object(obj) => (
+                    obj.get("cid").and_then(Value::as_str).map(str::to_string),
+                    obj.get("node_id")
+                        .or_else(|| obj.get("nodeId"))
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                ),
+                _ => (None, None),
+            };
+            if let Some(cid) = cid {
+                result.push(SubDeviceStatus {
+                    cid,
+                    online,
+                    node_id,
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Wi-Fi details reported by the device in reply to `QueryWifi`, see [`Device::query_wifi`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WifiInfo {
+    /// Network name, if the device reports it.
+    pub ssid: Option<String>,
+    /// Signal strength in dBm (e.g. -60), if the device reports it.
+    pub rssi: Option<i32>,
+    /// Full response payload, for fields not modeled above.
+    pub raw: Value,
+}
+
+impl WifiInfo {
+    /// Parses a `QueryWifi` response.
+    ///
+    /// Looks for `signal`/`rssi` and `ssid` at the root or under `data`. The signal may be a
+    /// number or a numeric string.
+    ///
+    /// ```
+    /// use rustuya::device::WifiInfo;
+    ///
+    /// let info = WifiInfo::parse(r#"{"signal": "-60"}"#).unwrap();
+    /// assert_eq!(info.rssi, Some(-60));
+    ///
+    /// let info = WifiInfo::parse(r#"{"data": {"ssid": "home", "rssi": -72}}"#).unwrap();
+    /// assert_eq!(info.ssid.as_deref(), Some("home"));
+    /// assert_eq!(info.rssi, Some(-72));
+    /// ```
+    pub fn parse(payload: &str) -> Result<Self> {
+        let val: Value = serde_json::from_str(payload)?;
+        if let Some(code) = val.get(keys::ERR_CODE).and_then(Value::as_u64) {
+            return Err(TuyaError::from_code(code as u32));
+        }
+
+        let field = |keys: &[&str]| {
+            let data = val.get("data");
+            keys.iter()
+                .find_map(|k| val.get(*k).or_else(|| data.and_then(|d| d.get(*k))))
+        };
+        let rssi = field(&["signal", "rssi"]).and_then(|v| match v {
+            Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        });
+        let ssid = field(&["ssid"]).and_then(Value::as_str).map(str::to_string);
+
+        Ok(Self {
+            ssid,
+            rssi,
+            raw: val,
+        })
+    }
+}
+
+/// Extracts the `dps` object from a status payload (`dps` at the root or under `data`).
+fn extract_dps(payload: &str) -> Option<Value> {
+    let val: Value = serde_json::from_str(payload).ok()?;
+    val.get("dps")
+        .or_else(|| val.get("data").and_then(|d| d.get("dps")))
+        .filter(|d| d.is_object())
+        .cloned()
+}
+
+/// Builds a DPS object from `(dp_id, value)` pairs.
+pub(crate) fn dps_object<I, K>(dps: I) -> Value
+where
+    I: IntoIterator<Item = (K, Value)>,
+    K: ToString,
+{
+    Value::Object(
+        dps.into_iter()
+            .map(|(dp, value)| (dp.to_string(), value))
+            .collect(),
+    )
+}
+
+fn extract_schema(payload: &str) -> Option<Value> {
+    let val: Value = serde_json::from_str(payload).ok()?;
+    let schema = val
+        .get("schema")
+        .or_else(|| val.get("data").and_then(|d| d.get("schema")))?;
+    match schema {
+        Value::String(s) => serde_json::from_str(s).ok(),
+        Value::Array(_) | Value::Object(_) => Some(schema.clone()),
+        _ => None,
+    }
+}
+
+fn sub_discover_query() -> Value {
+    serde_json::json!({
+        "cids": [],
+        keys::REQ_TYPE: "subdev_online_stat_query"
+    })
 }
 
 enum DeviceCommand {
@@ -106,16 +591,34 @@ enum DeviceCommand {
         command: CommandType,
         data: Option<Value>,
         cid: Option<String>,
+        /// Overrides the device timeout when waiting for the reply.
+        response_timeout: Option<Duration>,
         resp_tx: oneshot::Sender<Result<Option<TuyaMessage>>>,
     },
+    Pipeline {
+        requests: Vec<(CommandType, Option<Value>, Option<String>)>,
+        resp_tx: oneshot::Sender<Vec<Result<TuyaMessage>>>,
+    },
     Disconnect,
     ConnectNow,
+    /// Resolves once every command queued before it has been handled.
+    Flush(oneshot::Sender<()>),
 }
 
 impl DeviceCommand {
     fn respond(self, result: Result<Option<TuyaMessage>>) {
-        if let DeviceCommand::Request { resp_tx, .. } = self {
-            let _ = resp_tx.send(result);
+        match self {
+            DeviceCommand::Request { resp_tx, .. } => {
+                let _ = resp_tx.send(result);
+            }
+            DeviceCommand::Pipeline { requests, resp_tx } => {
+                let result = result.and_then(|msg| msg.ok_or(TuyaError::InvalidPayload));
+                let _ = resp_tx.send(requests.iter().map(|_| result.clone()).collect());
+            }
+            DeviceCommand::Flush(done) => {
+                let _ = done.send(());
+            }
+            DeviceCommand::Disconnect | DeviceCommand::ConnectNow => {}
         }
     }
 }
@@ -133,17 +636,46 @@ struct DeviceState {
     real_ip: String,
     version: Version,
     port: u16,
+    port_explicit: bool,
     dev_type: DeviceType,
     state: ConnectionState,
     last_received: Instant,
     last_sent: Instant,
+    last_command: Option<Instant>,
     persist: bool,
     session_key: Option<Vec<u8>>,
     failure_count: u32,
     success_count: u32,
     force_discovery: bool,
     timeout: Duration,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+    min_command_interval: Duration,
+    handshake_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    refresh_dps_on_status: Vec<u32>,
+    status_on_connect: bool,
+    verify_checksum: bool,
+    resync_frames: bool,
+    max_frame_size: u32,
+    max_prefix_scan: usize,
+    legacy_query: bool,
+    control_base64: bool,
+    auto_device22: bool,
+    proxy: Option<ProxyConfig>,
+    metrics: DeviceMetrics,
+    connected_once: bool,
+    local_key: Vec<u8>,
+    dry_run: bool,
+    clock: Option<Clock>,
+    on_connect: Option<ConnectHook>,
+    on_disconnect: Option<DisconnectHook>,
+    wire_tap: Option<WireTap>,
+    draining: bool,
     cipher: Option<Arc<TuyaCipher>>,
+    event_buffer: usize,
+    replay_buffer: usize,
+    replay: VecDeque<TuyaMessage>,
 }
 
 pub struct DeviceBuilder {
@@ -152,10 +684,34 @@ pub struct DeviceBuilder {
     local_key: Vec<u8>,
     version: Version,
     dev_type: DeviceType,
-    port: u16,
+    port: Option<u16>,
     persist: bool,
     timeout: Duration,
     nowait: bool,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+    min_command_interval: Duration,
+    handshake_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    refresh_dps_on_status: Vec<u32>,
+    status_on_connect: bool,
+    verify_checksum: bool,
+    resync_frames: bool,
+    max_frame_size: u32,
+    max_prefix_scan: usize,
+    legacy_query: bool,
+    control_base64: bool,
+    auto_device22: bool,
+    proxy: Option<ProxyConfig>,
+    event_buffer: usize,
+    replay_buffer: usize,
+    dry_run: bool,
+    clock: Option<Clock>,
+    on_connect: Option<ConnectHook>,
+    on_disconnect: Option<DisconnectHook>,
+    wire_tap: Option<WireTap>,
+    shared: bool,
+    stagger: bool,
 }
 
 impl DeviceBuilder {
@@ -170,10 +726,34 @@ impl DeviceBuilder {
             local_key: local_key.into(),
             version: Version::Auto,
             dev_type: DeviceType::Auto,
-            port: 6668,
+            port: None,
             persist: true,
             timeout: Duration::from_secs(10),
             nowait: false,
+            tcp_nodelay: false,
+            tcp_keepalive: None,
+            min_command_interval: Duration::ZERO,
+            handshake_timeout: None,
+            request_timeout: None,
+            refresh_dps_on_status: Vec::new(),
+            status_on_connect: false,
+            verify_checksum: true,
+            resync_frames: true,
+            max_frame_size: 256 * 1024,
+            max_prefix_scan: MAX_PREFIX_SCAN,
+            legacy_query: false,
+            control_base64: false,
+            auto_device22: true,
+            proxy: None,
+            event_buffer: CHAN_BROADCAST_CAPACITY_DEFAULT,
+            replay_buffer: 0,
+            dry_run: false,
+            clock: None,
+            on_connect: None,
+            on_disconnect: None,
+            wire_tap: None,
+            shared: true,
+            stagger: true,
         }
     }
 
@@ -192,12 +772,20 @@ impl DeviceBuilder {
         self
     }
 
+    /// Overrides the device TCP port. If unset, the port announced during discovery
+    /// is used when available, otherwise the standard port 6668.
     #[must_use]
     pub fn port(mut self, port: u16) -> Self {
-        self.port = port;
+        self.port = Some(port);
         self
     }
 
+    /// Keeps the connection alive and reconnects with backoff (default: true).
+    ///
+    /// With `false`, a failed connection is not retried in the background; the next
+    /// command connects on demand instead. While disconnected, commands wait for that
+    /// attempt and fail with its actual error (e.g. `ConnectionFailed`,
+    /// `KeyOrVersionError`, `Timeout`), even in nowait mode.
     #[must_use]
     pub fn persist(mut self, persist: bool) -> Self {
         self.persist = persist;
@@ -210,25 +798,456 @@ impl DeviceBuilder {
         self
     }
 
+    /// Returns from `status()`, `set_value()`, `set_dps()`, and `request()` as soon as
+    /// the command is queued, with `Ok(None)`, instead of waiting for the correlated reply
+    /// (default: false). Replies and failures still reach `listener()`. Can be changed
+    /// later with `Device::set_nowait`. Methods that need the reply itself, such as
+    /// `pipeline()` and `SubDevice::request_await()`, always wait.
+    ///
+    /// ```
+    /// use rustuya::{Device, Version};
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// rt.block_on(async {
+    ///     // Unreachable address: only nowait mode returns without waiting for the timeout.
+    ///     let device = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///         .address("192.0.2.1")
+    ///         .version(Version::V3_3)
+    ///         .nowait(true)
+    ///         .run();
+    ///     assert!(matches!(device.set_value(1, true).await, Ok(None)));
+    /// });
+    /// ```
     #[must_use]
     pub fn nowait(mut self, nowait: bool) -> Self {
         self.nowait = nowait;
         self
     }
 
+    /// Sets `TCP_NODELAY` on the device socket to reduce command latency.
+    #[must_use]
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Enables `SO_KEEPALIVE` on the device socket with the given idle time and probe interval.
+    /// Helps detect half-open connections (e.g., behind NAT) faster than the app-level heartbeat.
+    #[must_use]
+    pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
+
+    /// Sets the minimum time between packets sent to the device.
+    /// Commands that arrive faster are queued and delayed, never dropped.
+    #[must_use]
+    pub fn min_command_interval(mut self, interval: Duration) -> Self {
+        self.min_command_interval = interval;
+        self
+    }
+
+    /// Caps the whole v3.4/v3.5 session key negotiation, failing the connection attempt with
+    /// `Timeout` once exceeded (default: none, each handshake packet waits up to `timeout`).
+    /// Keeps a slow or misconfigured device from stalling connects.
+    #[must_use]
+    pub fn handshake_timeout(mut self, budget: Duration) -> Self {
+        self.handshake_timeout = Some(budget);
+        self
+    }
+
+    /// Sets how long a command waits for its reply once sent, separately from `timeout`,
+    /// which still bounds connecting, handshaking and socket reads (default: `timeout`). Lets calls to
+    /// a device that accepts the connection but never answers fail fast with `Timeout`.
+    #[must_use]
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes every `status()` call first send `UpdateDps` for these DP indices, for devices
+    /// that leave some DPs (often energy readings) out of a plain status query (default: none).
+    #[must_use]
+    pub fn refresh_dps_on_status(mut self, dps: Vec<u32>) -> Self {
+        self.refresh_dps_on_status = dps;
+        self
+    }
+
+    /// Queries the status right after every successful connection, including reconnects, so
+    /// listeners get a fresh snapshot without polling (default: false). The reply is
+    /// delivered to `listener()` like any other status report.
+    #[must_use]
+    pub fn status_on_connect(mut self, enabled: bool) -> Self {
+        self.status_on_connect = enabled;
+        self
+    }
+
+    /// Controls CRC32/HMAC verification of incoming 55AA frames (default: true).
+    /// When disabled, mismatches are logged as warnings instead of dropping the packet.
+    /// Only meant for misbehaving third-party firmware.
+    #[must_use]
+    pub fn verify_checksum(mut self, verify: bool) -> Self {
+        self.verify_checksum = verify;
+        self
+    }
+
+    /// Controls recovery from malformed frames (default: true). A frame whose header is
+    /// invalid or whose suffix is missing is reported as an `ERR_PAYLOAD` message and the
+    /// bytes after its prefix are scanned again for the next packet, so a corrupt length
+    /// field cannot swallow the frames behind it. Recoveries are counted in
+    /// `metrics().resynced_frames`.
+    #[must_use]
+    pub fn resync_frames(mut self, enabled: bool) -> Self {
+        self.resync_frames = enabled;
+        self
+    }
+
+    /// Largest payload length a frame header may announce, in bytes (default: `256 * 1024`).
+    /// Larger frames are rejected before their body is allocated and reported as an
+    /// `ERR_PAYLOAD` message on `listener()`; the connection stays open.
+    ///
+    /// ```
+    /// use futures_util::StreamExt;
+    /// use rustuya::protocol::{TuyaMessage, pack_message};
+    /// use rustuya::{Device, Version};
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    /// let port = listener.local_addr().unwrap().port();
+    /// tokio::spawn(async move {
+    ///     let (mut sock, _) = listener.accept().await.unwrap();
+    ///     // 55AA header announcing a 100 MB body, followed by a valid heartbeat frame.
+    ///     let mut data = vec![0, 0, 0x55, 0xaa, 0, 0, 0, 1, 0, 0, 0, 0x09];
+    ///     data.extend_from_slice(&100_000_000u32.to_be_bytes());
+    ///     let heartbeat = TuyaMessage { seqno: 2, cmd: 0x09, ..Default::default() };
+    ///     data.extend(pack_message(&heartbeat, None).unwrap());
+    ///     sock.write_all(&data).await.unwrap();
+    ///     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    /// });
+    ///
+    /// let device = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///     .address("127.0.0.1")
+    ///     .port(port)
+    ///     .version(Version::V3_3)
+    ///     .run();
+    /// let mut events = Box::pin(device.listener());
+    /// let reply = loop {
+    ///     let msg = events.next().await.unwrap().unwrap();
+    ///     let reply: serde_json::Value = serde_json::from_slice(&msg.payload).unwrap();
+    ///     if reply["errorCode"] != 0 {
+    ///         break reply; // skip the connection event
+    ///     }
+    /// };
+    /// assert_eq!(reply["errorCode"], 904);
+    /// assert!(reply["payloadStr"].as_str().unwrap().contains("max_frame_size"));
+    /// assert!(device.is_connected());
+    /// # device.close().await;
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = u32::try_from(size).unwrap_or(u32::MAX);
+        self
+    }
+
+    /// How many junk bytes may be skipped while looking for the next packet prefix before
+    /// the stream is reported as desynchronized with an `ERR_PAYLOAD` message (default:
+    /// 1024, see [`MAX_PREFIX_SCAN`]).
+    #[must_use]
+    pub fn max_prefix_scan(mut self, max_skip: usize) -> Self {
+        self.max_prefix_scan = max_skip;
+        self
+    }
+
+    /// Sends `DpQuery` (0x0a) and `Control` (0x07) as they are on v3.4+ instead of upgrading
+    /// them to `DpQueryNew` (0x10) and `ControlNew` (0x0d) (default: false). Payloads keep
+    /// the v3.4+ layout. Some v3.4 firmware ignores the new commands and only answers the
+    /// legacy ones; try this if `status()` times out or returns no DPs on a v3.4 device whose
+    /// handshake succeeds.
+    #[must_use]
+    pub fn legacy_query(mut self, enabled: bool) -> Self {
+        self.legacy_query = enabled;
+        self
+    }
+
+    /// Sends v3.3 `Control` payloads in the signed base64 form (`b"3.3"` + MD5 digest +
+    /// base64 of the ECB ciphertext, as on v3.1) instead of raw ECB (default: false). Some
+    /// v3.3 firmware only accepts this form and ignores commands otherwise; replies in
+    /// either form are decoded regardless. Has no effect on other versions.
+    #[must_use]
+    pub fn control_base64(mut self, enabled: bool) -> Self {
+        self.control_base64 = enabled;
+        self
+    }
+
+    /// Enables the device22 auto-detection heuristics while `dev_type` is `Auto` (default: true).
+    /// When disabled, the device type is never switched behind your back and decode failures
+    /// are reported as regular errors. Frames without a payload, such as heartbeat acks,
+    /// never trigger the switch.
+    ///
+    /// ```
+    /// use futures_util::StreamExt;
+    /// use rustuya::protocol::{DeviceType, TuyaMessage, pack_message};
+    /// use rustuya::{Device, Version};
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    /// let port = listener.local_addr().unwrap().port();
+    /// tokio::spawn(async move {
+    ///     let (mut sock, _) = listener.accept().await.unwrap();
+    ///     let ack = |seqno| {
+    ///         pack_message(&TuyaMessage { seqno, cmd: 0x09, ..Default::default() }, None)
+    ///     };
+    ///     let mut data = Vec::new();
+    ///     for seqno in 1..=3 {
+    ///         data.extend(ack(seqno).unwrap());
+    ///     }
+    ///     // An empty ack with a broken CRC, which the device reports as ERR_PAYLOAD.
+    ///     let mut bad = ack(4).unwrap();
+    ///     let crc = bad.len() - 8;
+    ///     bad[crc] ^= 0xff;
+    ///     data.extend(bad);
+    ///     sock.write_all(&data).await.unwrap();
+    ///     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    /// });
+    ///
+    /// let device = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///     .address("127.0.0.1")
+    ///     .port(port)
+    ///     .version(Version::V3_3)
+    ///     .run();
+    /// let mut events = Box::pin(device.listener());
+    /// let reply = loop {
+    ///     let msg = events.next().await.unwrap().unwrap();
+    ///     let reply: serde_json::Value = serde_json::from_slice(&msg.payload).unwrap();
+    ///     if reply["errorCode"] != 0 {
+    ///         break reply;
+    ///     }
+    /// };
+    /// assert_eq!(reply["errorCode"], 904);
+    /// assert_eq!(device.dev_type(), DeviceType::Auto);
+    /// # device.close().await;
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn auto_device22(mut self, enabled: bool) -> Self {
+        self.auto_device22 = enabled;
+        self
+    }
+
+    /// Routes the device TCP connection through a SOCKS5 proxy.
+    ///
+    /// UDP discovery is not proxied, so set an explicit `address` and `version` when the
+    /// device is only reachable through the proxy.
+    #[must_use]
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets how many events are buffered for each listener (default: 128).
+    /// Listeners that fall further behind than this miss events and receive a lag notice.
+    #[must_use]
+    pub fn event_buffer(mut self, capacity: usize) -> Self {
+        self.event_buffer = capacity.max(1);
+        self
+    }
+
+    /// Keeps the last `n` messages and replays them to each new `listener()` before live
+    /// ones (default: 0, disabled), so a late subscriber can show recent events. This is
+    /// the raw message history, including error events, not a DP cache.
+    #[must_use]
+    pub fn replay_buffer(mut self, n: usize) -> Self {
+        self.replay_buffer = n;
+        self
+    }
+
+    /// Builds packets for every command without connecting or writing to the socket
+    /// (default: false). Packets are published on `Device::outbound_packets()` and
+    /// commands return `Ok(None)`, so automations can be tested without hardware.
+    #[must_use]
+    pub fn dry_run(mut self, enable: bool) -> Self {
+        self.dry_run = enable;
+        self
+    }
+
+    /// Sets the clock used for the `t` timestamp in payloads, in seconds since the Unix
+    /// epoch (default: system time). Use it to correct for clock skew or to make payloads
+    /// deterministic in tests.
+    ///
+    /// ```
+    /// use futures_util::StreamExt;
+    /// use rustuya::{Device, Version};
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// rt.block_on(async {
+    ///     let device = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///         .address("192.0.2.1")
+    ///         .version(Version::V3_3)
+    ///         .clock(|| 1_700_000_000)
+    ///         .dry_run(true)
+    ///         .run();
+    ///     let mut packets = Box::pin(device.outbound_packets());
+    ///
+    ///     device.set_value(1, true).await.unwrap();
+    ///     let payload = packets.next().await.unwrap().payload;
+    ///     assert!(String::from_utf8(payload).unwrap().contains("1700000000"));
+    /// });
+    /// ```
+    #[must_use]
+    pub fn clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Registers a callback invoked from the connection task after every successful
+    /// connection, including reconnects. Keep it short; spawn a task for async work.
+    ///
+    /// ```no_run
+    /// use rustuya::Device;
+    ///
+    /// # async fn example() {
+    /// let device = Device::builder("DEVICE_ID", "DEVICE_KEY")
+    ///     .on_connect(|device| {
+    ///         // Prime the DP cache on every (re)connect.
+    ///         let device = device.clone();
+    ///         tokio::spawn(async move {
+    ///             let _ = device.status().await;
+    ///         });
+    ///     })
+    ///     .on_disconnect(|device, err| eprintln!("{} disconnected: {err}", device.id()))
+    ///     .run();
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn on_connect<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Device) + Send + Sync + 'static,
+    {
+        self.on_connect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers a callback invoked from the connection task when an established
+    /// connection is lost, with the error that ended it. Not called on `stop()`.
+    #[must_use]
+    pub fn on_disconnect<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Device, &TuyaError) + Send + Sync + 'static,
+    {
+        self.on_disconnect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers a callback that sees every raw frame on the device socket: outbound frames
+    /// once packed, just before they are written, and inbound frames once fully read,
+    /// before decryption or checksum checks. It runs inline on the connection task, so
+    /// take timestamps inside it and keep it short. Useful for comparing nonstandard
+    /// framing against a packet capture; `outbound_packets()` and `listener()` are not
+    /// affected.
+    ///
+    /// ```no_run
+    /// use rustuya::Device;
+    /// use rustuya::device::Direction;
+    ///
+    /// let device = Device::builder("DEVICE_ID", "DEVICE_KEY")
+    ///     .wire_tap(|dir, bytes| {
+    ///         let arrow = if dir == Direction::Outbound { ">>" } else { "<<" };
+    ///         eprintln!("{:?} {arrow} {}", std::time::SystemTime::now(), hex::encode(bytes));
+    ///     })
+    ///     .run();
+    /// ```
+    #[must_use]
+    pub fn wire_tap<F>(mut self, tap: F) -> Self
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.wire_tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Reuses the running device with the same ID and local key, if any, instead of
+    /// opening another connection (default: true). Gateways accept only a few concurrent
+    /// LAN connections, so separately constructed handles share one. The first device's
+    /// settings win (differing ones are logged as a warning), and `set_nowait()` or
+    /// `stop()` on any handle affects them all. Disable to force a separate connection.
+    ///
+    /// ```
+    /// use rustuya::Device;
+    ///
+    /// let a = Device::builder("bf0123456789abcdef", "0123456789abcdef").dry_run(true).run();
+    /// let b = Device::builder("bf0123456789abcdef", "0123456789abcdef").dry_run(true).run();
+    /// let c = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///     .dry_run(true)
+    ///     .shared(false)
+    ///     .run();
+    /// assert_eq!(Device::registry_snapshot().len(), 2);
+    /// # drop((a, b, c));
+    /// ```
+    #[must_use]
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Starts the device's connection task and returns its handle.
+    ///
+    /// With `shared` enabled (the default), a running device with the same ID and local key
+    /// is reused instead of opening a second connection. This builder's settings are then
+    /// dropped in favor of the running device's, and a warning names the ones that differ.
+    /// The handle shares `set_nowait()` and `stop()` with every other handle of that
+    /// device. Use `shared(false)` for an independent connection.
     #[must_use]
     pub fn run(self) -> Device {
         Device::with_builder(self)
     }
+
+    /// Discovers the device (if address or version is `Auto`), connects immediately,
+    /// and waits for the first successful handshake.
+    ///
+    /// Returns an error if the device cannot be found or the handshake fails within the timeout.
+    pub async fn connect(mut self) -> Result<Device> {
+        let ip_auto = matches!(self.address.as_str(), "" | ADDR_AUTO);
+        let resolved = if ip_auto || self.version == Version::Auto {
+            Some(
+                get_scanner()
+                    .discover_device_internal(&self.id, false)
+                    .await?
+                    .ok_or_else(|| TuyaError::DeviceNotFound(self.id.clone()))?,
+            )
+        } else {
+            None
+        };
+
+        self.stagger = false;
+        let (device, existing) = Device::start(self, resolved.as_ref());
+        match device.wait_for_connect(device.timeout() * 2, true).await {
+            Ok(()) => Ok(device),
+            Err(e) => {
+                // Leave a device shared with other handles running.
+                if !existing {
+                    device.stop().await;
+                }
+                Err(e)
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Device {
     id: String,
-    local_key: Vec<u8>,
     state: Arc<RwLock<DeviceState>>,
     tx: Option<mpsc::Sender<DeviceCommand>>,
     pub(crate) broadcast_tx: tokio::sync::broadcast::Sender<TuyaMessage>,
+    outbound_tx: tokio::sync::broadcast::Sender<OutboundPacket>,
+    connection_tx: tokio::sync::broadcast::Sender<ConnectionEvent>,
+    report_tx: tokio::sync::broadcast::Sender<StatusReport>,
     cancel_token: CancellationToken,
     nowait: Arc<AtomicBool>,
 }
@@ -244,6 +1263,10 @@ impl Drop for Device {
 
 impl Device {
     /// Creates a new device with default settings and starts the connection task.
+    ///
+    /// If a device with the same ID and local key is already running, its connection is
+    /// reused instead (see [`DeviceBuilder::shared`]): the returned handle shares that
+    /// device's settings, `set_nowait()` and `stop()`.
     pub fn new<I, K>(id: I, local_key: K) -> Self
     where
         I: Into<String>,
@@ -261,56 +1284,237 @@ impl Device {
         DeviceBuilder::new(id, local_key)
     }
 
+    /// Discovers the device on the local network and returns it once connected.
+    /// See [`DeviceBuilder::connect`].
+    pub async fn connect<I, K>(id: I, local_key: K) -> Result<Self>
+    where
+        I: Into<String>,
+        K: Into<Vec<u8>>,
+    {
+        DeviceBuilder::new(id, local_key).connect().await
+    }
+
     pub(crate) fn with_builder(builder: DeviceBuilder) -> Self {
+        Self::start(builder, None).0
+    }
+
+    /// Starts a device for `builder`, or reuses the running one it shares. `resolved` holds
+    /// what discovery found for the `Auto` address and version; a reused device that has
+    /// not resolved them yet adopts it. Also returns whether a running device was reused.
+    fn start(mut builder: DeviceBuilder, resolved: Option<&DiscoveryResult>) -> (Self, bool) {
+        // Hold the registry lock from lookup to insert so concurrent builders for the
+        // same device cannot both start a connection.
+        let mut registry = REGISTRY.lock();
+        registry.retain(|entry| entry.state.strong_count() > 0);
+        if builder.shared
+            && let Some(device) = Self::find_in(&registry, &builder.id, &builder.local_key)
+        {
+            let ignored = device.ignored_settings(&builder);
+            if ignored.is_empty() {
+                debug!("Reusing the running connection for device {}", device.id);
+            } else {
+                warn!(
+                    "Device {} is already running; reusing its connection and ignoring these \
+                     settings of the new builder: {}. Use shared(false) for a separate connection",
+                    device.id,
+                    ignored.join(", ")
+                );
+            }
+            if let Some(result) = resolved {
+                device.adopt_discovery(result);
+            }
+            return (device, true);
+        }
+
+        if let Some(result) = resolved {
+            if matches!(builder.address.as_str(), "" | ADDR_AUTO) {
+                builder.address.clone_from(&result.ip);
+            }
+            if builder.port.is_none() {
+                builder.port = result.port;
+            }
+            if builder.version == Version::Auto
+                && let Some(v) = result.version
+            {
+                builder.version = v;
+            }
+        }
+
         let (addr, ip) = match builder.address.as_str() {
             "" | ADDR_AUTO => (ADDR_AUTO.to_string(), String::new()),
             _ => (builder.address.clone(), builder.address),
         };
 
-        let (broadcast_tx, _) = tokio::sync::broadcast::channel(CHAN_BROADCAST_CAPACITY);
+        let (broadcast_tx, _) = tokio::sync::broadcast::channel(builder.event_buffer);
+        let (outbound_tx, _) = tokio::sync::broadcast::channel(builder.event_buffer);
+        let (connection_tx, _) = tokio::sync::broadcast::channel(builder.event_buffer);
+        let (report_tx, _) = tokio::sync::broadcast::channel(builder.event_buffer);
         let (tx, rx) = mpsc::channel(CHAN_MPSC_CAPACITY);
         let state = DeviceState {
             config_address: addr,
             real_ip: ip,
             version: builder.version,
-            port: builder.port,
+            port: builder.port.unwrap_or(PORT_DEFAULT),
+            port_explicit: builder.port.is_some(),
             dev_type: builder.dev_type,
             state: ConnectionState::Disconnected,
             last_received: Instant::now(),
             last_sent: Instant::now(),
+            last_command: None,
             persist: builder.persist,
             session_key: None,
             failure_count: 0,
             success_count: 0,
             force_discovery: false,
             timeout: builder.timeout,
+            tcp_nodelay: builder.tcp_nodelay,
+            tcp_keepalive: builder.tcp_keepalive,
+            min_command_interval: builder.min_command_interval,
+            handshake_timeout: builder.handshake_timeout,
+            request_timeout: builder.request_timeout,
+            refresh_dps_on_status: builder.refresh_dps_on_status,
+            status_on_connect: builder.status_on_connect,
+            verify_checksum: builder.verify_checksum,
+            resync_frames: builder.resync_frames,
+            max_frame_size: builder.max_frame_size,
+            max_prefix_scan: builder.max_prefix_scan,
+            legacy_query: builder.legacy_query,
+            control_base64: builder.control_base64,
+            auto_device22: builder.auto_device22,
+            proxy: builder.proxy,
+            metrics: DeviceMetrics::default(),
+            connected_once: false,
             cipher: TuyaCipher::new(&builder.local_key).ok().map(Arc::new),
+            local_key: builder.local_key,
+            dry_run: builder.dry_run,
+            clock: builder.clock,
+            on_connect: builder.on_connect,
+            on_disconnect: builder.on_disconnect,
+            wire_tap: builder.wire_tap,
+            draining: false,
+            event_buffer: builder.event_buffer,
+            replay_buffer: builder.replay_buffer,
+            replay: VecDeque::with_capacity(builder.replay_buffer),
         };
 
         let device = Self {
             id: builder.id,
-            local_key: builder.local_key,
             state: Arc::new(RwLock::new(state)),
             tx: Some(tx),
             broadcast_tx,
+            outbound_tx,
+            connection_tx,
+            report_tx,
             cancel_token: CancellationToken::new(),
             nowait: Arc::new(AtomicBool::new(builder.nowait)),
         };
+        registry.push(RegistryEntry::new(&device, builder.shared));
+        drop(registry);
 
+        let stagger = builder.stagger;
         let cancel_token = device.cancel_token.clone();
         let d_clone = device.clone();
         let d_id = device.id.clone();
-        crate::runtime::spawn(async move {
+        let task = async move {
             tokio::select! {
                 () = cancel_token.cancelled() => {
                     debug!("Device {d_id} connection task stopped via token");
                 }
-                () = d_clone.run_connection_task(rx) => {
+                () = d_clone.run_connection_task(rx, stagger) => {
                     debug!("Device {d_id} connection task finished");
                 }
             }
+        };
+        #[cfg(feature = "tracing")]
+        let task =
+            tracing::Instrument::instrument(task, tracing::info_span!("device", id = %device.id));
+        crate::runtime::spawn(task);
+        (device, false)
+    }
+
+    /// Fills in the address, port and version from `result` where this device has not
+    /// resolved them yet.
+    fn adopt_discovery(&self, result: &DiscoveryResult) {
+        self.with_state_mut(|s| {
+            if s.config_address == ADDR_AUTO && s.real_ip.is_empty() {
+                s.real_ip.clone_from(&result.ip);
+            }
+            if let Some(port) = result.port
+                && !s.port_explicit
+            {
+                s.port = port;
+            }
+            if s.version == Version::Auto
+                && let Some(v) = result.version
+            {
+                s.version = v;
+            }
         });
-        device
+    }
+
+    /// Names the settings of `builder` that differ from this running device and would be
+    /// dropped by reusing it. `Auto` address, version and type mean no preference. Hooks
+    /// and proxies cannot be compared, so any set on `builder` are reported.
+    fn ignored_settings(&self, builder: &DeviceBuilder) -> Vec<&'static str> {
+        let nowait = self.nowait();
+        self.with_state(|s| {
+            let b = builder;
+            [
+                (
+                    "address",
+                    !matches!(b.address.as_str(), "" | ADDR_AUTO) && b.address != s.config_address,
+                ),
+                ("port", b.port.is_some_and(|port| port != s.port)),
+                (
+                    "version",
+                    b.version != Version::Auto && b.version != s.version,
+                ),
+                (
+                    "dev_type",
+                    b.dev_type != DeviceType::Auto && b.dev_type != s.dev_type,
+                ),
+                ("persist", b.persist != s.persist),
+                ("timeout", b.timeout != s.timeout),
+                ("nowait", b.nowait != nowait),
+                ("tcp_nodelay", b.tcp_nodelay != s.tcp_nodelay),
+                ("tcp_keepalive", b.tcp_keepalive != s.tcp_keepalive),
+                (
+                    "min_command_interval",
+                    b.min_command_interval != s.min_command_interval,
+                ),
+                (
+                    "handshake_timeout",
+                    b.handshake_timeout != s.handshake_timeout,
+                ),
+                ("request_timeout", b.request_timeout != s.request_timeout),
+                (
+                    "refresh_dps_on_status",
+                    b.refresh_dps_on_status != s.refresh_dps_on_status,
+                ),
+                (
+                    "status_on_connect",
+                    b.status_on_connect != s.status_on_connect,
+                ),
+                ("verify_checksum", b.verify_checksum != s.verify_checksum),
+                ("resync_frames", b.resync_frames != s.resync_frames),
+                ("max_frame_size", b.max_frame_size != s.max_frame_size),
+                ("max_prefix_scan", b.max_prefix_scan != s.max_prefix_scan),
+                ("legacy_query", b.legacy_query != s.legacy_query),
+                ("control_base64", b.control_base64 != s.control_base64),
+                ("auto_device22", b.auto_device22 != s.auto_device22),
+                ("event_buffer", b.event_buffer != s.event_buffer),
+                ("replay_buffer", b.replay_buffer != s.replay_buffer),
+                ("dry_run", b.dry_run != s.dry_run),
+                ("proxy", b.proxy.is_some()),
+                ("clock", b.clock.is_some()),
+                ("on_connect", b.on_connect.is_some()),
+                ("on_disconnect", b.on_disconnect.is_some()),
+                ("wire_tap", b.wire_tap.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, differs)| differs.then_some(name))
+            .collect()
+        })
     }
 
     #[must_use]
@@ -324,8 +1528,8 @@ impl Device {
     }
 
     #[must_use]
-    pub fn local_key(&self) -> &[u8] {
-        &self.local_key
+    pub fn local_key(&self) -> Vec<u8> {
+        self.with_state(|s| s.local_key.clone())
     }
 
     #[must_use]
@@ -345,16 +1549,57 @@ impl Device {
         self.with_state(|s| s.config_address.clone())
     }
 
+    /// Returns the IP the device was last resolved to, or `None` before discovery has run
+    /// for a device configured as "Auto". Unlike `address()`, never falls back to the
+    /// configured address.
+    #[must_use]
+    pub fn resolved_ip(&self) -> Option<String> {
+        self.with_state(|s| (!s.real_ip.is_empty()).then(|| s.real_ip.clone()))
+    }
+
     #[must_use]
     pub fn version(&self) -> Version {
         self.with_state(|s| s.version)
     }
 
+    /// Whether the version was set explicitly or reported by discovery, rather than guessed.
+    fn version_known(&self) -> bool {
+        self.version() != Version::Auto
+    }
+
     #[must_use]
     pub fn is_connected(&self) -> bool {
         self.with_state(|s| s.state == ConnectionState::Connected)
     }
 
+    /// Lists every device still alive in this process as `(id, ref_count, connected)`.
+    ///
+    /// `ref_count` counts all handles sharing the device, including the clones held by its
+    /// own background tasks. A device that was stopped but still shows up here is being
+    /// kept alive by a handle somewhere. The registry lock is only held while copying the
+    /// list, never while reading device state.
+    #[must_use]
+    pub fn registry_snapshot() -> Vec<(String, usize, bool)> {
+        let entries: Vec<_> = {
+            let mut registry = REGISTRY.lock();
+            registry.retain(|entry| entry.state.strong_count() > 0);
+            registry
+                .iter()
+                .map(|entry| (entry.id.clone(), entry.state.clone()))
+                .collect()
+        };
+
+        entries
+            .into_iter()
+            .filter_map(|(id, weak)| {
+                let state = weak.upgrade()?;
+                let ref_count = Arc::strong_count(&state) - 1;
+                let connected = state.read().state == ConnectionState::Connected;
+                Some((id, ref_count, connected))
+            })
+            .collect()
+    }
+
     #[must_use]
     pub fn is_stopped(&self) -> bool {
         self.with_state(|s| s.state == ConnectionState::Stopped)
@@ -366,6 +1611,13 @@ impl Device {
         self.with_state(|s| s.timeout)
     }
 
+    /// Returns how long a sent command waits for its reply: the `request_timeout` if one
+    /// was set, otherwise `timeout()`.
+    #[must_use]
+    pub fn request_timeout(&self) -> Duration {
+        self.with_state(|s| s.request_timeout.unwrap_or(s.timeout))
+    }
+
     #[must_use]
     pub fn port(&self) -> u16 {
         self.with_state(|s| s.port)
@@ -381,6 +1633,86 @@ impl Device {
     pub fn nowait(&self) -> bool {
         self.nowait.load(Ordering::Relaxed)
     }
+
+    #[must_use]
+    pub fn tcp_nodelay(&self) -> bool {
+        self.with_state(|s| s.tcp_nodelay)
+    }
+
+    #[must_use]
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.with_state(|s| s.tcp_keepalive)
+    }
+
+    /// Returns the session key negotiated during the v3.4/v3.5 handshake, for diagnostics.
+    ///
+    /// This is the final key used to encrypt traffic (after the protocol's key derivation).
+    /// Returns `None` before a successful handshake, after a disconnect, and for v3.3 and below.
+    #[must_use]
+    pub fn session_key(&self) -> Option<Vec<u8>> {
+        self.with_state(|s| s.session_key.clone())
+    }
+
+    /// Returns a snapshot of the device's traffic and connection counters.
+    #[must_use]
+    pub fn metrics(&self) -> DeviceMetrics {
+        self.with_state(|s| DeviceMetrics {
+            failure_count: s.failure_count,
+            ..s.metrics.clone()
+        })
+    }
+
+    /// Returns a dump of the device's configuration and connection state, meant to be
+    /// pasted into bug reports (serializable with the `serde` feature). The local and
+    /// session keys are left out.
+    ///
+    /// ```
+    /// use rustuya::Device;
+    ///
+    /// let device = Device::builder("bf0123456789abcdef", "fedcba9876543210")
+    ///     .address("192.168.1.40")
+    ///     .dry_run(true)
+    ///     .run();
+    /// let diag = device.diagnostics();
+    /// assert_eq!(diag.config_address, "192.168.1.40");
+    /// assert!(!diag.has_session_key);
+    ///
+    /// #[cfg(feature = "serde")]
+    /// {
+    ///     let report = serde_json::to_string(&diag).unwrap();
+    ///     assert!(!report.contains("fedcba9876543210"));
+    /// }
+    /// ```
+    #[must_use]
+    pub fn diagnostics(&self) -> DeviceDiagnostics {
+        let nowait = self.nowait();
+        self.with_state(|s| DeviceDiagnostics {
+            id: self.id.clone(),
+            config_address: s.config_address.clone(),
+            resolved_ip: (!s.real_ip.is_empty()).then(|| s.real_ip.clone()),
+            port: s.port,
+            version: s.version,
+            dev_type: s.dev_type.as_str().to_string(),
+            connected: s.state == ConnectionState::Connected,
+            stopped: s.state == ConnectionState::Stopped,
+            persist: s.persist,
+            nowait,
+            failure_count: s.failure_count,
+            last_received: s.last_received.elapsed(),
+            last_sent: s.last_sent.elapsed(),
+            has_session_key: s.session_key.is_some(),
+            metrics: DeviceMetrics {
+                failure_count: s.failure_count,
+                ..s.metrics.clone()
+            },
+        })
+    }
+
+    /// Returns the minimum time enforced between packets sent to the device.
+    #[must_use]
+    pub fn min_command_interval(&self) -> Duration {
+        self.with_state(|s| s.min_command_interval)
+    }
 }
 
 impl Device {
@@ -388,59 +1720,320 @@ impl Device {
         self.with_state_mut(|s| s.persist = persist);
     }
 
-    pub fn set_timeout(&self, timeout: Duration) {
-        self.with_state_mut(|s| s.timeout = timeout);
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.with_state_mut(|s| s.timeout = timeout);
+    }
+
+    pub fn set_port(&self, port: u16) {
+        self.with_state_mut(|s| {
+            s.port = port;
+            s.port_explicit = true;
+        });
+    }
+
+    pub fn set_min_command_interval(&self, interval: Duration) {
+        self.with_state_mut(|s| s.min_command_interval = interval);
+    }
+
+    /// Sets whether requests should wait for a response from the device.
+    /// If true, methods like `status()` and `set_value()` will return immediately after
+    /// dispatching the command, without waiting for the network response.
+    pub fn set_nowait(&self, nowait: bool) {
+        self.nowait.store(nowait, Ordering::Relaxed);
+    }
+
+    pub fn set_version<V: Into<Version>>(&self, version: V) {
+        let ver = version.into();
+
+        self.with_state_mut(|s| {
+            s.version = ver;
+            // If dev_type is Auto, we can either leave it as Auto (to allow future detection)
+            // or initialize it to Default. Given the user's requirement that only Auto
+            // allows switching, we should keep it as Auto if the user hasn't specified Default.
+        });
+    }
+
+    pub fn set_dev_type<DT: Into<DeviceType>>(&self, dev_type: DT) {
+        self.with_state_mut(|s| s.dev_type = dev_type.into());
+    }
+
+    pub fn set_address<A: Into<String>>(&self, address: A) {
+        let addr = address.into();
+        self.with_state_mut(|s| {
+            s.config_address = addr;
+            s.force_discovery = true; // Force discovery to update real_ip if needed
+        });
+    }
+}
+
+impl Device {
+    /// Returns a stream of only the errors this device reports: connection failures,
+    /// timeouts, rejected keys, undecodable packets, and so on.
+    ///
+    /// The synthetic `errorCode` events from the listener are decoded back into
+    /// `TuyaError`s. Status updates, the "connected" event (code 0), and device22
+    /// detection notices (code 908) are not included.
+    pub fn errors(&self) -> impl Stream<Item = TuyaError> + Send + 'static {
+        let mut rx = self.broadcast_tx.subscribe();
+        async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        if let Some(e) = event_error(&msg) {
+                            yield e;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    /// Streams connection attempts and the backoff waits between them, e.g. to show
+    /// "attempt 3, next retry in 120s" while a device is unreachable. Only events emitted
+    /// after subscribing are seen.
+    pub fn connection_events(&self) -> impl Stream<Item = ConnectionEvent> + Send + 'static {
+        let mut rx = self.connection_tx.subscribe();
+        async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    /// Streams the status reports the device pushes on its own (cmd 0x08), such as after a
+    /// physical button press, separately from replies to queries. Devices also push one
+    /// after applying a set command; those are flagged with `after_command`. Only reports
+    /// received after subscribing are seen.
+    pub fn status_reports(&self) -> impl Stream<Item = StatusReport> + Send + 'static {
+        let mut rx = self.report_tx.subscribe();
+        async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(report) => yield report,
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    /// Returns a stream of the packets written to the device, including heartbeats and
+    /// handshakes. In dry-run mode (`DeviceBuilder::dry_run`) these are the packets that
+    /// would have been sent. Packets are dropped for consumers that fall behind.
+    ///
+    /// ```
+    /// use futures_util::StreamExt;
+    /// use rustuya::{Device, Version, protocol::decode_packet};
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// rt.block_on(async {
+    ///     let device = Device::builder("bf0123456789abcdef", "0123456789abcdef")
+    ///         .address("192.0.2.1")
+    ///         .version(Version::V3_3)
+    ///         .dry_run(true)
+    ///         .run();
+    ///     let mut packets = Box::pin(device.outbound_packets());
+    ///
+    ///     assert_eq!(device.set_value(1, true).await.unwrap(), None);
+    ///     let packet = packets.next().await.unwrap();
+    ///     let decoded = decode_packet(&packet.packet, b"0123456789abcdef", Version::V3_3).unwrap();
+    ///     assert_eq!(decoded.dp(1), Some(serde_json::json!(true)));
+    /// });
+    /// ```
+    pub fn outbound_packets(&self) -> impl Stream<Item = OutboundPacket> + Send + 'static {
+        let mut rx = self.outbound_tx.subscribe();
+        async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(packet) => yield packet,
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    /// Returns an asynchronous stream of messages/events from this device.
+    ///
+    /// If the consumer falls behind by more than the event buffer (see
+    /// `DeviceBuilder::event_buffer`), the skipped messages are dropped and an event with
+    /// `errorCode` 906 (`ERR_STATE`) and a `skipped` count is yielded in their place.
+    /// With `DeviceBuilder::replay_buffer` set, recent messages are yielded first.
+    pub fn listener(&self) -> impl Stream<Item = Result<TuyaMessage>> + Send + 'static {
+        let (mut rx, replay) = self.subscribe_with_replay();
+        let id = self.id.clone();
+        let prefix = self.event_prefix();
+        async_stream::stream! {
+            for msg in replay {
+                yield Ok(msg);
+            }
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        if !msg.payload.is_empty() {
+                            yield Ok(msg);
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("Listener for {id} lagged behind, skipped {n} messages");
+                        yield Ok(lagged_message(n, prefix));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
     }
 
-    pub fn set_port(&self, port: u16) {
-        self.with_state_mut(|s| s.port = port);
+    /// Queries the device status.
+    ///
+    /// With `refresh_dps_on_status` set, an `UpdateDps` for those DPs is sent first. Its
+    /// failure is only logged, since many devices never answer it.
+    pub async fn status(&self) -> Result<Option<String>> {
+        let refresh = self.with_state(|s| s.refresh_dps_on_status.clone());
+        if !refresh.is_empty()
+            && let Err(e) = self
+                .request(CommandType::UpdateDps, Some(Value::from(refresh)), None)
+                .await
+        {
+            debug!("UpdateDps before status failed for {}: {e}", self.id);
+        }
+        self.request(CommandType::DpQuery, None, None).await
     }
 
-    /// Sets whether requests should wait for a response from the device.
-    /// If true, methods like `status()` and `set_value()` will return immediately after
-    /// dispatching the command, without waiting for the network response.
-    pub fn set_nowait(&self, nowait: bool) {
-        self.nowait.store(nowait, Ordering::Relaxed);
+    /// Queries the device status and returns the parsed JSON response.
+    ///
+    /// Cancel-safe: dropping the returned future (e.g., when another `tokio::select!` branch
+    /// wins) withdraws the request. If it has not been sent yet it is skipped, and if it is
+    /// waiting for a reply the connection task stops waiting and moves on to the next command.
+    ///
+    /// Error responses carrying an `errorCode` are returned as `Err`. With `nowait` enabled
+    /// there is no response to parse and `InvalidPayload` is returned.
+    pub async fn status_request(&self) -> Result<Value> {
+        let payload = self.status().await?.ok_or(TuyaError::InvalidPayload)?;
+        let val: Value = serde_json::from_str(&payload)?;
+        match val.get(keys::ERR_CODE).and_then(Value::as_u64) {
+            Some(code) if code != u64::from(ERR_SUCCESS) => Err(TuyaError::from_code(code as u32)),
+            _ => Ok(val),
+        }
     }
 
-    pub fn set_version<V: Into<Version>>(&self, version: V) {
-        let ver = version.into();
+    /// Queries the device status, then keeps collecting DP reports for `window` and returns
+    /// all DPs merged into one object.
+    ///
+    /// For devices that spread their DPs over several packets after a status query. The
+    /// window starts once the status reply arrives; later reports overwrite earlier values
+    /// for the same DP. Reports carrying a `cid` belong to gateway sub-devices and are
+    /// skipped. Fails with `InvalidPayload` if no DPs were received.
+    pub async fn status_collect(&self, window: Duration) -> Result<Value> {
+        let mut rx = self.broadcast_tx.subscribe();
+        let mut dps = serde_json::Map::new();
+        let mut merge = |payload: &[u8]| {
+            let Ok(val) = serde_json::from_slice::<Value>(payload) else {
+                return;
+            };
+            let data = val.get("data");
+            if val
+                .get("cid")
+                .or_else(|| data.and_then(|d| d.get("cid")))
+                .is_some()
+            {
+                return;
+            }
+            if let Some(Value::Object(map)) = val.get("dps").or_else(|| data?.get("dps")) {
+                dps.extend(map.clone());
+            }
+        };
 
-        self.with_state_mut(|s| {
-            s.version = ver;
-            // If dev_type is Auto, we can either leave it as Auto (to allow future detection)
-            // or initialize it to Default. Given the user's requirement that only Auto
-            // allows switching, we should keep it as Auto if the user hasn't specified Default.
-        });
-    }
+        if let Some(payload) = self.status().await? {
+            merge(payload.as_bytes());
+        }
 
-    pub fn set_dev_type<DT: Into<DeviceType>>(&self, dev_type: DT) {
-        self.with_state_mut(|s| s.dev_type = dev_type.into());
+        let start = Instant::now();
+        loop {
+            let remaining = window.saturating_sub(start.elapsed());
+            match timeout(remaining, rx.recv()).await {
+                Ok(Ok(msg)) if msg.cmd != 0 => merge(&msg.payload),
+                Ok(Ok(_)) => {}
+                Ok(Err(RecvError::Lagged(n))) => {
+                    warn!("status_collect for {} skipped {n} messages", self.id);
+                }
+                Ok(Err(RecvError::Closed)) | Err(_) => break,
+            }
+        }
+
+        if dps.is_empty() {
+            Err(TuyaError::InvalidPayload)
+        } else {
+            Ok(Value::Object(dps))
+        }
     }
 
-    pub fn set_address<A: Into<String>>(&self, address: A) {
-        let addr = address.into();
-        self.with_state_mut(|s| {
-            s.config_address = addr;
-            s.force_discovery = true; // Force discovery to update real_ip if needed
+    /// Finds the protocol version the device speaks by trying each one, newest first.
+    ///
+    /// For every candidate (3.5 down to 3.1) a short-lived connection is opened to the
+    /// device's address, the handshake is performed where the version has one, and a status
+    /// query is sent. The first version that returns a valid status wins. This device's own
+    /// connection and settings are left untouched; apply the result with `set_version` or
+    /// store it for later. Fails with `KeyOrVersionError` if no version works.
+    pub async fn probe_version(&self) -> Result<Version> {
+        let (config_addr, real_ip, port, dev_type, proxy) = self.with_state(|s| {
+            (
+                s.config_address.clone(),
+                s.real_ip.clone(),
+                s.port,
+                s.dev_type,
+                s.proxy.clone(),
+            )
         });
-    }
-}
+        let address = if !matches!(config_addr.as_str(), "" | "0.0.0.0" | ADDR_AUTO) {
+            config_addr
+        } else if !real_ip.is_empty() {
+            real_ip
+        } else {
+            self.resolve_address().await?
+        };
 
-impl Device {
-    pub fn listener(&self) -> impl Stream<Item = Result<TuyaMessage>> + Send + 'static {
-        let mut rx = self.broadcast_tx.subscribe();
-        async_stream::stream! {
-            while let Ok(msg) = rx.recv().await {
-                if !msg.payload.is_empty() {
-                    yield Ok(msg);
+        for version in [
+            Version::V3_5,
+            Version::V3_4,
+            Version::V3_3,
+            Version::V3_2,
+            Version::V3_1,
+        ] {
+            debug!("Probing {} at {address}:{port} with v{version}", self.id);
+            let mut builder = DeviceBuilder::new(self.id.clone(), self.local_key())
+                .address(address.clone())
+                .port(port)
+                .version(version)
+                .dev_type(dev_type)
+                .timeout(self.timeout())
+                .persist(false)
+                .shared(false);
+            builder.stagger = false;
+            if let Some(proxy) = proxy.clone() {
+                builder = builder.proxy(proxy);
+            }
+
+            let probe = builder.run();
+            let result = probe.status_request().await;
+            probe.stop().await;
+
+            match result {
+                Ok(_) => {
+                    info!("Device {} responds to protocol v{version}", self.id);
+                    return Ok(version);
                 }
+                Err(e) => debug!("Probe of {} with v{version} failed: {e}", self.id),
             }
         }
-    }
 
-    pub async fn status(&self) -> Result<Option<String>> {
-        self.request(CommandType::DpQuery, None, None).await
+        Err(TuyaError::KeyOrVersionError)
     }
 
     /// Sets multiple DP values at once.
@@ -449,6 +2042,25 @@ impl Device {
         self.request(CommandType::Control, Some(dps), None).await
     }
 
+    /// Sets several DPs in one command from `(dp_id, value)` pairs, without building the
+    /// JSON object by hand. Later pairs win if a DP ID repeats.
+    ///
+    /// ```no_run
+    /// # async fn example(device: rustuya::Device) -> rustuya::error::Result<()> {
+    /// use serde_json::json;
+    ///
+    /// device.set_multiple([(1, json!(true)), (20, json!(500))]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_multiple<I, K>(&self, dps: I) -> Result<Option<String>>
+    where
+        I: IntoIterator<Item = (K, Value)>,
+        K: ToString,
+    {
+        self.set_dps(dps_object(dps)).await
+    }
+
     /// Sets a single DP value by its ID.
     /// The `dp_id` can be provided as any type that can be converted to a String (e.g., u32, &str).
     /// The `value` can be any type that implements `Serialize` (e.g., bool, i32, String, `serde_json::Value`).
@@ -465,15 +2077,118 @@ impl Device {
         }
     }
 
+    /// Sets DP values one at a time, in order, as separate `Control` commands.
+    ///
+    /// Each step waits for the device reply before sending the next one. In nowait mode
+    /// there is no reply to wait for, so a short fixed delay (200ms) is inserted between steps
+    /// instead. Unlike `set_dps`, the values are not applied atomically.
+    pub async fn set_sequence(&self, steps: Vec<(u32, Value)>) -> Vec<Result<Option<String>>> {
+        let mut results = Vec::with_capacity(steps.len());
+        let count = steps.len();
+        for (i, (dp_id, value)) in steps.into_iter().enumerate() {
+            results.push(self.set_value(dp_id, value).await);
+            if self.nowait() && i + 1 < count {
+                sleep(SLEEP_SEQUENCE_NOWAIT).await;
+            }
+        }
+        results
+    }
+
+    /// Triggers a scene stored locally on the device (typically a gateway) by its scene ID.
+    ///
+    /// Sent as `SceneExecute` (0x11) and supported on v3.3 and later; v3.1/v3.2 return
+    /// `FunctionNotSupported`. Gateways that expect the `LanScene` (0xfe) frame instead can be
+    /// driven with `request(CommandType::LanScene, Some(json!(scene_id)), None)`, which uses the
+    /// same payload layout.
+    pub async fn execute_scene(&self, scene_id: &str) -> Result<Option<String>> {
+        if matches!(self.version(), Version::V3_1 | Version::V3_2) {
+            return Err(TuyaError::FunctionNotSupported);
+        }
+        self.request(
+            CommandType::SceneExecute,
+            Some(serde_json::json!(scene_id)),
+            None,
+        )
+        .await
+    }
+
     pub async fn sub_discover(&self) -> Result<Option<String>> {
-        let data = serde_json::json!({
-            "cids": [],
-            keys::REQ_TYPE: "subdev_online_stat_query"
-        });
-        self.request(CommandType::LanExtStream, Some(data), None)
+        self.request(CommandType::LanExtStream, Some(sub_discover_query()), None)
             .await
     }
 
+    /// Like `sub_discover`, but resends the query when no report arrives in time.
+    ///
+    /// Some v3.5 gateways only answer with an empty `LanExtStream` ACK and never send the
+    /// report. Each attempt waits up to `per_attempt_timeout`; the query is resent up to
+    /// `retries` more times before `TuyaError::Timeout` is returned.
+    pub async fn sub_discover_with_retry(
+        &self,
+        retries: u32,
+        per_attempt_timeout: Duration,
+    ) -> Result<Option<String>> {
+        for attempt in 0..=retries {
+            match self
+                .request_with_timeout(
+                    CommandType::LanExtStream,
+                    Some(sub_discover_query()),
+                    None,
+                    Some(per_attempt_timeout),
+                )
+                .await
+            {
+                Err(TuyaError::Timeout) => {
+                    debug!(
+                        "sub_discover attempt {}/{} timed out for {}",
+                        attempt + 1,
+                        retries + 1,
+                        self.id
+                    );
+                }
+                res => return res,
+            }
+        }
+        Err(TuyaError::Timeout)
+    }
+
+    /// Queries the gateway for its sub-devices and their online state.
+    ///
+    /// Not available in nowait mode, since the report is the response to the query.
+    pub async fn sub_devices(&self) -> Result<Vec<SubDeviceStatus>> {
+        match self.sub_discover().await? {
+            Some(payload) => parse_sub_device_report(&payload),
+            None => Err(TuyaError::InvalidPayload),
+        }
+    }
+
+    /// Asks the device for its Wi-Fi SSID and signal strength (`QueryWifi`, 0x0B).
+    ///
+    /// Not every firmware answers this command; those that do not time out. Not available
+    /// in nowait mode.
+    pub async fn query_wifi(&self) -> Result<WifiInfo> {
+        match self.request(CommandType::QueryWifi, None, None).await? {
+            Some(payload) => WifiInfo::parse(&payload),
+            None => Err(TuyaError::InvalidPayload),
+        }
+    }
+
+    /// Asks the device for its DP schema (`ReqDevInfo`, 0x25) and returns the raw schema JSON.
+    ///
+    /// The LAN protocol has no documented schema command; DP definitions normally come
+    /// from the Tuya cloud. Some newer firmware answers `ReqDevInfo` with a description
+    /// that carries a `schema` field (a JSON string or array), which is returned parsed.
+    /// Devices that time out, reply with an error, or include no schema fail with
+    /// `FunctionNotSupported`. Not available in nowait mode.
+    pub async fn query_schema(&self) -> Result<Value> {
+        let reply = match self.request(CommandType::ReqDevInfo, None, None).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return Err(TuyaError::InvalidPayload),
+            Err(TuyaError::Timeout) => return Err(TuyaError::FunctionNotSupported),
+            Err(e) => return Err(e),
+        };
+        extract_schema(&reply).ok_or(TuyaError::FunctionNotSupported)
+    }
+
     pub async fn receive(&self) -> Result<TuyaMessage> {
         let mut rx = self.broadcast_tx.subscribe();
         loop {
@@ -483,28 +2198,145 @@ impl Device {
                         return Ok(msg);
                     }
                 }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(
+                        "Receiver for {} lagged behind, skipped {n} messages",
+                        self.id
+                    );
+                }
                 Err(e) => return Err(TuyaError::Io(e.to_string())),
             }
         }
     }
 
+    /// Measures how long the device takes to respond, as a cheap liveness check.
+    ///
+    /// While connected, a heartbeat is sent and its ACK awaited (also in nowait mode). Otherwise
+    /// a plain TCP connection is opened to the device and closed right away, without a
+    /// handshake; the address is discovered first if not yet known. Fails with `Timeout` after
+    /// `request_timeout()` or `timeout()` respectively. Proxies are not used for the TCP probe.
+    pub async fn ping(&self) -> Result<Duration> {
+        if self.is_connected() {
+            let start = Instant::now();
+            self.pipeline(vec![(CommandType::HeartBeat, None, None)])
+                .await
+                .pop()
+                .unwrap_or(Err(TuyaError::Offline))?;
+            return Ok(start.elapsed());
+        }
+
+        let ip = match self.with_state(|s| s.real_ip.clone()) {
+            ip if !ip.is_empty() => ip,
+            _ => self.resolve_address().await?,
+        };
+        let port = self.port();
+        let start = Instant::now();
+        timeout(self.timeout(), TcpStream::connect((ip.as_str(), port)))
+            .await
+            .map_err(|_| TuyaError::Timeout)??;
+        Ok(start.elapsed())
+    }
+
+    /// Waits for the next message from the device that satisfies `pred`, e.g. a reply with a
+    /// given command or sequence number. Only messages arriving after the call are seen.
+    /// Unlike `receive()`, empty ACKs are passed to the predicate too. Fails with `Timeout`
+    /// if nothing matches within `wait`.
+    pub async fn receive_where<F>(&self, pred: F, wait: Duration) -> Result<TuyaMessage>
+    where
+        F: Fn(&TuyaMessage) -> bool,
+    {
+        let mut rx = self.broadcast_tx.subscribe();
+        let deadline = Instant::now() + wait;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match timeout(remaining, rx.recv()).await {
+                Ok(Ok(msg)) if pred(&msg) => return Ok(msg),
+                Ok(Ok(_)) => {}
+                Ok(Err(RecvError::Lagged(n))) => {
+                    warn!(
+                        "Receiver for {} lagged behind, skipped {n} messages",
+                        self.id
+                    );
+                }
+                Ok(Err(e)) => return Err(TuyaError::Io(e.to_string())),
+                Err(_) => return Err(TuyaError::Timeout),
+            }
+        }
+    }
+
+    /// Returns a handle to a sub-device reached through this gateway. Sub-devices always
+    /// share the gateway's connection; no additional TCP connection is opened.
     #[must_use]
     pub fn sub(&self, cid: &str) -> SubDevice {
         SubDevice::new(self.clone(), cid)
     }
 
+    /// Returns the running device with this ID and local key, if one was built with
+    /// `shared` enabled (the default).
+    fn find_in(registry: &[RegistryEntry], id: &str, local_key: &[u8]) -> Option<Device> {
+        registry
+            .iter()
+            .filter(|entry| entry.shared && entry.id == id)
+            .filter_map(RegistryEntry::upgrade)
+            .find(|device| device.with_state(|s| s.local_key == local_key))
+    }
+
+    /// Sends a command and waits for the matching reply (unless in nowait mode).
+    ///
+    /// Requests are queued and processed one at a time by the connection task, so concurrent
+    /// callers never share a pending reply. Replies are matched by command and CID rather than
+    /// by sequence number, since devices do not reliably echo the request seqno; a reply with an
+    /// unexpected or reused seqno is therefore still accepted. Every reply is also broadcast to
+    /// `listener()` subscribers.
     pub async fn request(
         &self,
         command: CommandType,
         data: Option<Value>,
         cid: Option<String>,
     ) -> Result<Option<String>> {
-        debug!("request: cmd={command:?}, data={data:?}");
+        self.request_with_timeout(command, data, cid, None).await
+    }
+
+    /// Sends several requests back-to-back without waiting in between, then collects the
+    /// replies as they arrive. Results are returned in request order.
+    ///
+    /// Each entry is `(command, data, cid)`; replies are matched by command and CID, so
+    /// this suits gateways where many sub-devices are queried at once. Requests share a
+    /// single response timeout. Some firmware drops packets that arrive too quickly; use
+    /// `DeviceBuilder::min_command_interval` to space them out. Always waits for replies,
+    /// even in nowait mode.
+    pub async fn pipeline(
+        &self,
+        requests: Vec<(CommandType, Option<Value>, Option<String>)>,
+    ) -> Vec<Result<TuyaMessage>> {
+        let count = requests.len();
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.send_to_task(DeviceCommand::Pipeline { requests, resp_tx })
+            .await;
+        resp_rx
+            .await
+            .unwrap_or_else(|_| (0..count).map(|_| Err(TuyaError::Offline)).collect())
+    }
+
+    async fn request_with_timeout(
+        &self,
+        command: CommandType,
+        data: Option<Value>,
+        cid: Option<String>,
+        response_timeout: Option<Duration>,
+    ) -> Result<Option<String>> {
+        debug!("request: cmd={command:?}");
+        trace_payload!("request data: {data:?}");
         let resp = self
             .send_command_to_task(|resp_tx| DeviceCommand::Request {
                 command,
                 data,
                 cid,
+                response_timeout,
                 resp_tx,
             })
             .await?;
@@ -537,6 +2369,27 @@ impl Device {
         }
     }
 
+    /// Stops the device after the commands already queued have been sent and answered.
+    ///
+    /// New commands are rejected with `Offline` from the moment this is called. Queued
+    /// commands are handled in order as usual (including on-demand connects) until they
+    /// are done or `timeout` elapses, whichever comes first; then the device stops as with
+    /// `stop()`, failing anything still pending.
+    pub async fn stop_graceful(&self, timeout: Duration) {
+        info!("Stopping device {} after pending commands", self.id);
+        self.with_state_mut(|s| s.draining = true);
+
+        let (done_tx, done_rx) = oneshot::channel();
+        self.send_to_task(DeviceCommand::Flush(done_tx)).await;
+        if tokio::time::timeout(timeout, done_rx).await.is_err() {
+            warn!(
+                "Device {} still had pending commands after {:?}",
+                self.id, timeout
+            );
+        }
+        self.stop().await;
+    }
+
     pub async fn stop(&self) {
         info!("Stopping device {} (explicit stop called)", self.id);
         self.with_state_mut(|state| {
@@ -546,10 +2399,50 @@ impl Device {
         self.close().await;
     }
 
+    /// Waits until the device is connected.
+    ///
+    /// Resolves immediately if already connected. Connection failures in between are not
+    /// errors (the background task keeps retrying); `TuyaError::Timeout` is returned if the
+    /// device is still not connected after `timeout`.
+    pub async fn wait_connected(&self, timeout: Duration) -> Result<()> {
+        self.wait_for_connect(timeout, false).await
+    }
+
     /// Forces the device to attempt a connection immediately, bypassing any backoff.
     pub async fn connect_now(&self) {
         self.send_to_task(DeviceCommand::ConnectNow).await;
     }
+
+    /// Clears the accumulated failure count and wakes the connection task from its backoff
+    /// sleep, so the next attempt happens now and later retries start from the shortest delay.
+    /// Useful after a known network outage has ended.
+    pub async fn reconnect_now(&self) {
+        self.with_state_mut(|s| {
+            s.failure_count = 0;
+            s.success_count = 0;
+        });
+        self.connect_now().await;
+    }
+
+    /// Replaces the local key (e.g. after re-pairing) and reconnects with it.
+    ///
+    /// The current connection, if any, is closed and a new handshake is started right away.
+    /// This handle, its clones, and existing listeners stay valid.
+    pub async fn set_local_key<K: Into<Vec<u8>>>(&self, local_key: K) {
+        let local_key = local_key.into();
+        let connected = self.with_state_mut(|s| {
+            s.local_key = local_key;
+            s.failure_count = 0;
+            s.success_count = 0;
+            s.state == ConnectionState::Connected
+        });
+        info!("Local key changed for device {}, reconnecting", self.id);
+
+        if connected {
+            self.send_to_task(DeviceCommand::Disconnect).await;
+        }
+        self.connect_now().await;
+    }
 }
 
 impl Device {
@@ -562,7 +2455,37 @@ impl Device {
     }
 
     fn broadcast_error(&self, code: u32, payload: Option<Value>) {
-        let _ = self.broadcast_tx.send(self.error_helper(code, payload));
+        self.publish(self.error_helper(code, payload));
+    }
+
+    /// Sends a message to listeners, recording it for replay if enabled.
+    fn publish(&self, msg: TuyaMessage) {
+        // Sent under the state lock so `subscribe_with_replay` sees each message exactly once.
+        self.with_state_mut(|s| {
+            if s.replay_buffer > 0 && !msg.payload.is_empty() {
+                if s.replay.len() >= s.replay_buffer {
+                    s.replay.pop_front();
+                }
+                s.replay.push_back(msg.clone());
+            }
+            let _ = self.broadcast_tx.send(msg);
+        });
+    }
+
+    /// Subscribes to the listener channel and returns the buffered messages to replay
+    /// first (see `DeviceBuilder::replay_buffer`).
+    pub(crate) fn subscribe_with_replay(
+        &self,
+    ) -> (
+        tokio::sync::broadcast::Receiver<TuyaMessage>,
+        Vec<TuyaMessage>,
+    ) {
+        self.with_state(|s| {
+            (
+                self.broadcast_tx.subscribe(),
+                s.replay.iter().cloned().collect(),
+            )
+        })
     }
 
     fn update_last_received(&self) {
@@ -573,6 +2496,31 @@ impl Device {
         self.state.write().last_sent = Instant::now();
     }
 
+    fn mark_connected(&self) {
+        self.with_state_mut(|s| {
+            s.state = ConnectionState::Connected;
+            s.metrics.current_backoff = None;
+            if s.connected_once {
+                s.metrics.reconnects += 1;
+            }
+            s.connected_once = true;
+        });
+
+        if let Some(hook) = self.with_state(|s| s.on_connect.clone()) {
+            hook(self);
+        }
+
+        if self.with_state(|s| s.status_on_connect) {
+            // Queued behind the connection task's current work, so it cannot be awaited here
+            let device = self.clone();
+            crate::runtime::spawn(async move {
+                if let Err(e) = device.status().await {
+                    debug!("Status after connect failed for {}: {e}", device.id);
+                }
+            });
+        }
+    }
+
     fn reset_failure_count(&self) {
         let mut state = self.state.write();
         state.success_count += 1;
@@ -586,7 +2534,56 @@ impl Device {
         }
     }
 
+    /// Waits for the connection task to report a successful connection.
+    /// With `fail_fast`, the first connection or handshake error is returned instead of
+    /// waiting on; other errors, such as a malformed frame, are skipped.
+    async fn wait_for_connect(&self, limit: Duration, fail_fast: bool) -> Result<()> {
+        let mut rx = self.broadcast_tx.subscribe();
+        if self.is_connected() {
+            return Ok(());
+        }
+
+        timeout(limit, async {
+            loop {
+                let msg = match rx.recv().await {
+                    Ok(msg) => msg,
+                    Err(RecvError::Lagged(_)) if self.is_connected() => return Ok(()),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Err(TuyaError::Offline),
+                };
+                let code = serde_json::from_slice::<Value>(&msg.payload)
+                    .ok()
+                    .and_then(|v| v.get(keys::ERR_CODE).and_then(Value::as_u64));
+
+                match code {
+                    Some(c) if c == u64::from(ERR_SUCCESS) => return Ok(()),
+                    Some(c)
+                        if fail_fast
+                            && matches!(
+                                u32::try_from(c),
+                                Ok(ERR_CONNECT | ERR_OFFLINE | ERR_KEY_OR_VER | ERR_AUTH_REJECTED)
+                            ) =>
+                    {
+                        return Err(TuyaError::from_code(c as u32));
+                    }
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .unwrap_or(Err(TuyaError::Timeout))
+    }
+
     async fn send_to_task(&self, cmd: DeviceCommand) {
+        if matches!(
+            cmd,
+            DeviceCommand::Request { .. } | DeviceCommand::Pipeline { .. }
+        ) && self.with_state(|s| s.draining)
+        {
+            debug!("Rejecting command for device {}: stopping", self.id);
+            cmd.respond(Err(TuyaError::Offline));
+            return;
+        }
         if let Some(tx) = &self.tx {
             if let Err(e) = tx.send(cmd).await {
                 error!("Failed to queue command for device {}: {}", self.id, e);
@@ -603,9 +2600,12 @@ impl Device {
         &self,
         cmd_generator: impl FnOnce(oneshot::Sender<Result<Option<TuyaMessage>>>) -> DeviceCommand,
     ) -> Result<Option<TuyaMessage>> {
+        // Without persist, a disconnected device connects for this command, so wait for
+        // the outcome to report the real connection error.
+        let fail_fast = !self.with_state(|s| s.persist) && !self.is_connected();
         let (resp_tx, resp_rx) = oneshot::channel();
         self.send_to_task(cmd_generator(resp_tx)).await;
-        if !self.nowait.load(Ordering::Relaxed) {
+        if !self.nowait.load(Ordering::Relaxed) || fail_fast {
             resp_rx.await.map_err(|_| TuyaError::Offline)?
         } else {
             Ok(None)
@@ -613,6 +2613,9 @@ impl Device {
     }
 
     fn get_timestamp(&self) -> u64 {
+        if let Some(clock) = self.with_state(|s| s.clock.clone()) {
+            return clock();
+        }
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
@@ -630,6 +2633,9 @@ pub struct DeviceEvent {
 }
 
 /// Merges multiple device listeners into a single stream of events.
+///
+/// Each device is read through its own broadcast channel, so a consumer that only cares about
+/// one device should use [`Device::listener`] directly instead of filtering this stream.
 pub fn unified_listener(
     devices: Vec<Device>,
 ) -> impl Stream<Item = Result<DeviceEvent>> + Send + 'static {
@@ -653,11 +2659,97 @@ pub fn unified_listener(
     select_all(streams)
 }
 
+/// Streams `(device_id, connected)` whenever one of the devices connects or disconnects,
+/// starting with the current state of each. Suited to live status tables that would
+/// otherwise poll `is_connected()` or [`Device::registry_snapshot`].
+///
+/// Holds no strong reference to the devices; a device's entry ends once it is dropped.
+pub fn connection_changes(
+    devices: Vec<Device>,
+) -> impl Stream<Item = (String, bool)> + Send + 'static {
+    use futures_util::StreamExt;
+    use futures_util::stream::select_all;
+
+    let streams = devices.into_iter().map(|device| {
+        let id = device.id.clone();
+        let state = Arc::downgrade(&device.state);
+        let mut rx = device.broadcast_tx.subscribe();
+        async_stream::stream! {
+            let is_connected = |state: &Weak<RwLock<DeviceState>>| {
+                state
+                    .upgrade()
+                    .map(|s| s.read().state == ConnectionState::Connected)
+            };
+            let Some(mut last) = is_connected(&state) else {
+                return;
+            };
+            yield (id.clone(), last);
+            // Connects, disconnects and failed attempts are announced on the listener channel, so
+            // the state only needs checking when an event arrives.
+            while let Ok(_) | Err(RecvError::Lagged(_)) = rx.recv().await {
+                let Some(connected) = is_connected(&state) else {
+                    break;
+                };
+                if connected != last {
+                    last = connected;
+                    yield (id.clone(), connected);
+                }
+            }
+        }
+        .boxed()
+    });
+
+    select_all(streams)
+}
+
+/// Queries every device for its sub-devices concurrently and returns them keyed by
+/// gateway ID. Each device gets two attempts of 2 seconds (see
+/// [`Device::sub_discover_with_retry`]); devices that fail, as plain non-gateway devices
+/// do with `Timeout`, are left out. The call therefore takes at most about 4 seconds,
+/// plus the time to connect.
+///
+/// ```
+/// use rustuya::Device;
+/// use rustuya::device::discover_all_sub_devices;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// // Dry-run devices never answer, so neither is reported as a gateway.
+/// let plug = Device::builder("bf0123456789abcdef", "0123456789abcdef").dry_run(true).run();
+/// let bulb = Device::builder("bf0123456789abcdeg", "0123456789abcdef").dry_run(true).run();
+/// assert!(discover_all_sub_devices(vec![plug, bulb]).await.is_empty());
+/// # });
+/// ```
+pub async fn discover_all_sub_devices(
+    devices: Vec<Device>,
+) -> HashMap<String, Vec<SubDeviceStatus>> {
+    let queries = devices.into_iter().map(|device| async move {
+        let report = device
+            .sub_discover_with_retry(SUB_DISCOVER_ALL_RETRIES, SUB_DISCOVER_ALL_TIMEOUT)
+            .await
+            .and_then(|reply| reply.ok_or(TuyaError::InvalidPayload))
+            .and_then(|payload| parse_sub_device_report(&payload));
+        match report {
+            Ok(subs) => Some((device.id.clone(), subs)),
+            Err(e) => {
+                debug!("Skipping {} in sub-device discovery: {}", device.id, e);
+                None
+            }
+        }
+    });
+    futures_util::future::join_all(queries)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
 impl Device {
-    async fn run_connection_task(&self, mut rx: mpsc::Receiver<DeviceCommand>) {
-        let jitter = {
+    async fn run_connection_task(&self, mut rx: mpsc::Receiver<DeviceCommand>, stagger: bool) {
+        let jitter = if stagger {
             let mut rng = rand::rng();
             Duration::from_millis(u64::from(rng.next_u32() % 5000))
+        } else {
+            Duration::ZERO
         };
 
         debug!(
@@ -665,6 +2757,11 @@ impl Device {
             self.id, jitter
         );
 
+        if self.with_state(|s| s.dry_run) {
+            self.run_dry_run(rx).await;
+            return;
+        }
+
         // Stagger connection attempts
         tokio::select! {
             () = self.cancel_token.cancelled() => return,
@@ -689,7 +2786,7 @@ impl Device {
                     let mut seqno = 1u32;
 
                     // 1. Connect and handshake
-                    let (stream, initial_cmd) = match self
+                    let (stream, leftover, initial_cmd) = match self
                         .try_connect_with_backoff(&mut rx, &mut seqno)
                         .await
                     {
@@ -699,7 +2796,7 @@ impl Device {
 
                     // 2. Connection maintenance
                     let result = self
-                        .maintain_connection(stream, &mut rx, &mut seqno, &mut heartbeat_interval, initial_cmd)
+                        .maintain_connection(stream, leftover, &mut rx, &mut seqno, &mut heartbeat_interval, initial_cmd)
                         .await;
 
                     self.handle_disconnect(result.as_ref().err().cloned());
@@ -735,12 +2832,14 @@ impl Device {
     async fn maintain_connection(
         &self,
         stream: TcpStream,
+        leftover: Vec<u8>,
         rx: &mut mpsc::Receiver<DeviceCommand>,
         seqno: &mut u32,
         heartbeat_interval: &mut tokio::time::Interval,
         initial_cmd: Option<DeviceCommand>,
     ) -> Result<()> {
-        let (mut read_half, mut write_half) = stream.into_split();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut read_half = FrameReader::with_pending(read_half, leftover);
         let (internal_tx, mut internal_rx) = mpsc::channel::<TuyaError>(1);
 
         // Process initial command if exists
@@ -777,7 +2876,10 @@ impl Device {
                                 let err = if e.kind() == std::io::ErrorKind::UnexpectedEof {
                                     if packets_received > 0 {
                                         TuyaError::Io("Connection reset".to_string())
+                                    } else if device_clone.version_known() {
+                                        TuyaError::AuthRejected
                                     } else {
+                                        // With a guessed version, a wrong version is just as likely.
                                         TuyaError::KeyOrVersionError
                                     }
                                 } else {
@@ -842,7 +2944,7 @@ impl Device {
         &self,
         rx: &mut mpsc::Receiver<DeviceCommand>,
         seqno: &mut u32,
-    ) -> Option<(TcpStream, Option<DeviceCommand>)> {
+    ) -> Option<(TcpStream, Vec<u8>, Option<DeviceCommand>)> {
         loop {
             if self.is_stopped() {
                 self.drain_rx(rx, TuyaError::Offline, true);
@@ -853,43 +2955,57 @@ impl Device {
             *seqno = 1;
 
             // Wait before retry if failed
-            let backoff = self.with_state(|s| {
-                if s.failure_count > 0 {
+            let (backoff, attempt, last_error) = self.with_state(|s| {
+                let backoff = if s.failure_count > 0 {
                     Some(self.get_backoff_duration(s.failure_count - 1))
                 } else {
                     None
-                }
+                };
+                (backoff, s.failure_count + 1, s.metrics.last_error.clone())
             });
 
+            self.with_state_mut(|s| s.metrics.current_backoff = backoff);
             if let Some(b) = backoff {
                 warn!(
                     "Waiting {}s before next connection attempt for {}",
                     b.as_secs(),
                     self.id
                 );
+                let _ = self.connection_tx.send(ConnectionEvent::Backoff {
+                    attempt,
+                    error: last_error,
+                    backoff: b,
+                });
                 self.wait_for_backoff(rx, b).await?;
             }
 
+            let _ = self
+                .connection_tx
+                .send(ConnectionEvent::Attempt { attempt });
+            let persist = self.with_state(|s| s.persist);
             let result = timeout(self.timeout() * 2, self.connect_and_handshake(seqno)).await;
             if let Ok(Ok(s)) = result {
-                self.with_state_mut(|s| s.state = ConnectionState::Connected);
+                self.mark_connected();
                 info!(
                     "Connected to device {} ({})",
                     self.id,
                     self.with_state(|s| s.real_ip.clone())
                 );
                 self.broadcast_error(ERR_SUCCESS, None);
-                return Some((s, None));
+                return Some((s.0, s.1, None));
             } else {
+                // Persistent devices treat an overall timeout as offline, which forces
+                // rediscovery below; one-shot callers get the timeout itself.
                 let e = match result {
                     Ok(Err(e)) => e,
+                    _ if !persist => TuyaError::Timeout,
                     _ => TuyaError::Offline,
                 };
 
                 self.handle_connection_error(&e).await;
                 self.drain_rx(rx, e.clone(), false);
 
-                if !self.with_state(|s| s.persist) {
+                if !persist {
                     warn!(
                         "Connection failed (persist: false) for {}: {}. Waiting for next command.",
                         self.id, e
@@ -898,26 +3014,34 @@ impl Device {
                     loop {
                         match rx.recv().await {
                             Some(DeviceCommand::ConnectNow) => break,
-                            Some(cmd @ DeviceCommand::Request { .. }) => {
+                            Some(
+                                cmd @ (DeviceCommand::Request { .. }
+                                | DeviceCommand::Pipeline { .. }),
+                            ) => {
+                                let attempt = self.with_state(|s| s.failure_count + 1);
+                                let _ = self
+                                    .connection_tx
+                                    .send(ConnectionEvent::Attempt { attempt });
                                 let retry_result =
                                     timeout(self.timeout() * 2, self.connect_and_handshake(seqno))
                                         .await;
 
                                 if let Ok(Ok(s)) = retry_result {
-                                    self.with_state_mut(|s| s.state = ConnectionState::Connected);
+                                    self.mark_connected();
                                     info!("Connected to {} on demand", self.id);
                                     self.broadcast_error(ERR_SUCCESS, None);
-                                    return Some((s, Some(cmd)));
+                                    return Some((s.0, s.1, Some(cmd)));
                                 } else {
                                     let err = match retry_result {
                                         Ok(Err(e)) => e,
-                                        _ => TuyaError::Offline,
+                                        _ => TuyaError::Timeout,
                                     };
                                     self.handle_connection_error(&err).await;
-                                    cmd.respond(Err(err.clone()));
+                                    cmd.respond(Err(err));
                                     self.broadcast_error(ERR_OFFLINE, None);
                                 }
                             }
+                            Some(cmd @ DeviceCommand::Flush(_)) => cmd.respond(Ok(None)),
                             Some(DeviceCommand::Disconnect) | None => return None,
                         }
                     }
@@ -929,7 +3053,9 @@ impl Device {
                     s.success_count = 0;
                     if s.config_address == ADDR_AUTO {
                         match e {
-                            TuyaError::KeyOrVersionError | TuyaError::Offline => {
+                            TuyaError::KeyOrVersionError
+                            | TuyaError::AuthRejected
+                            | TuyaError::Offline => {
                                 s.force_discovery = true;
                                 let _ = get_scanner().invalidate_cache(&self.id);
                             }
@@ -968,6 +3094,10 @@ impl Device {
                 cmd_opt = rx.recv() => {
                     if let Some(cmd) = cmd_opt {
                         if let DeviceCommand::ConnectNow = cmd { return Some(()) }
+                        if let DeviceCommand::Flush(_) = cmd {
+                            cmd.respond(Ok(None));
+                            continue;
+                        }
                         debug!("Rejecting command during backoff for device {}", self.id);
                         cmd.respond(Err(TuyaError::Offline));
                         self.broadcast_error(ERR_OFFLINE, None);
@@ -985,6 +3115,9 @@ impl Device {
                 s.state = ConnectionState::Disconnected;
             }
             s.session_key = None; // Clear session key on disconnect
+            if let Some(e) = &err {
+                s.metrics.last_error = Some(e.to_string());
+            }
         });
 
         if let Some(e) = err {
@@ -993,16 +3126,29 @@ impl Device {
                     "Device {} possibly has key or version mismatch (Error 914)",
                     self.id
                 );
+            } else if matches!(e, TuyaError::AuthRejected) {
+                warn!(
+                    "Device {} is reachable but rejected the local key (Error 915)",
+                    self.id
+                );
             } else if !self.is_stopped() {
                 debug!("Connection lost for device {} due to error: {}", self.id, e);
             }
 
             if !self.is_stopped() {
                 self.broadcast_error(e.code(), None);
+                self.run_disconnect_hook(&e);
             }
         } else if !self.is_stopped() {
             debug!("Connection closed normally for device {}", self.id);
             self.broadcast_error(ERR_OFFLINE, None);
+            self.run_disconnect_hook(&TuyaError::Offline);
+        }
+    }
+
+    fn run_disconnect_hook(&self, err: &TuyaError) {
+        if let Some(hook) = self.with_state(|s| s.on_disconnect.clone()) {
+            hook(self, err);
         }
     }
 
@@ -1011,6 +3157,7 @@ impl Device {
             if s.state != ConnectionState::Stopped {
                 s.state = ConnectionState::Disconnected;
             }
+            s.metrics.last_error = Some(e.to_string());
         });
         self.broadcast_error(e.code(), Some(serde_json::json!(format!("{}", e))));
     }
@@ -1030,30 +3177,120 @@ impl Device {
     // Protocol Implementation & Handshake
     // -------------------------------------------------------------------------
 
-    async fn connect_and_handshake(&self, seqno: &mut u32) -> Result<TcpStream> {
+    /// Connects and, on v3.4+, negotiates the session key. Returns the stream along with any
+    /// bytes already read from it past the handshake.
+    async fn connect_and_handshake(&self, seqno: &mut u32) -> Result<(TcpStream, Vec<u8>)> {
         let addr = self.resolve_address().await?;
         let port = self.with_state(|s| s.port);
 
-        info!("Connecting to device {} at {}:{}", self.id, addr, port);
-        let mut stream = timeout(self.timeout(), TcpStream::connect(format!("{addr}:{port}")))
-            .await
-            .map_err(|_| TuyaError::Timeout)?
-            .map_err(|e| match e.kind() {
-                std::io::ErrorKind::ConnectionRefused => TuyaError::ConnectionFailed,
-                _ => TuyaError::Io(e.to_string()),
-            })?;
+        let proxy = self.with_state(|s| s.proxy.clone());
+        let mut stream = if let Some(proxy) = proxy {
+            info!(
+                "Connecting to device {} at {}:{} via SOCKS5 proxy {}:{}",
+                self.id, addr, port, proxy.host, proxy.port
+            );
+            timeout(self.timeout(), Self::connect_via_proxy(&proxy, &addr, port))
+                .await
+                .map_err(|_| TuyaError::Timeout)??
+        } else {
+            info!("Connecting to device {} at {}:{}", self.id, addr, port);
+            timeout(self.timeout(), TcpStream::connect(format!("{addr}:{port}")))
+                .await
+                .map_err(|_| TuyaError::Timeout)?
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::ConnectionRefused => TuyaError::ConnectionFailed,
+                    _ => TuyaError::Io(e.to_string()),
+                })?
+        };
+        self.apply_socket_options(&stream);
+
+        let protocol = get_protocol(self.version(), self.dev_type());
+        if protocol.requires_session_key() {
+            // TCP is up, so with a known version a key failure here means the device
+            // rejected our local key.
+            let started = Instant::now();
+            let negotiation = self.negotiate_session_key(&mut stream, seqno);
+            let negotiated = match self.with_state(|s| s.handshake_timeout) {
+                Some(budget) => timeout(budget, negotiation).await.unwrap_or_else(|_| {
+                    warn!(
+                        "Session negotiation for {} exceeded {:?}, giving up",
+                        self.id, budget
+                    );
+                    Err(TuyaError::Timeout)
+                }),
+                None => negotiation.await,
+            }
+            .map_err(|e| {
+                if e.code() == ERR_KEY_OR_VER && self.version_known() {
+                    TuyaError::AuthRejected
+                } else {
+                    e
+                }
+            })?;
+            let elapsed = started.elapsed();
+            debug!("Session negotiation for {} took {:?}", self.id, elapsed);
+            self.with_state_mut(|s| s.metrics.last_handshake = Some(elapsed));
+            return Ok((stream, negotiated));
+        }
+
+        Ok((stream, Vec::new()))
+    }
+
+    async fn connect_via_proxy(proxy: &ProxyConfig, addr: &str, port: u16) -> Result<TcpStream> {
+        let proxy_addr = (proxy.host.as_str(), proxy.port);
+        let target = (addr, port);
+        let stream = match &proxy.auth {
+            Some((user, pass)) => {
+                Socks5Stream::connect_with_password(proxy_addr, target, user, pass).await
+            }
+            None => Socks5Stream::connect(proxy_addr, target).await,
+        }
+        .map_err(|e| match e {
+            tokio_socks::Error::Io(io) => TuyaError::from(io),
+            e => {
+                warn!(
+                    "SOCKS5 proxy {}:{} refused connection: {e}",
+                    proxy.host, proxy.port
+                );
+                TuyaError::ConnectionFailed
+            }
+        })?;
+        Ok(stream.into_inner())
+    }
+
+    fn apply_socket_options(&self, stream: &TcpStream) {
+        let (nodelay, keepalive) = self.with_state(|s| (s.tcp_nodelay, s.tcp_keepalive));
 
-        let protocol = get_protocol(self.version(), self.dev_type());
-        if protocol.requires_session_key()
-            && !self.negotiate_session_key(&mut stream, seqno).await?
-        {
-            return Err(TuyaError::KeyOrVersionError);
+        if nodelay && let Err(e) = stream.set_nodelay(true) {
+            warn!("Failed to set TCP_NODELAY for {}: {}", self.id, e);
         }
 
-        Ok(stream)
+        if let Some(interval) = keepalive {
+            let params = socket2::TcpKeepalive::new().with_time(interval);
+            #[cfg(any(
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "ios",
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "netbsd",
+                target_os = "windows",
+            ))]
+            let params = params.with_interval(interval);
+
+            if let Err(e) = socket2::SockRef::from(stream).set_tcp_keepalive(&params) {
+                warn!("Failed to set SO_KEEPALIVE for {}: {}", self.id, e);
+            }
+        }
     }
 
-    async fn negotiate_session_key(&self, stream: &mut TcpStream, seqno: &mut u32) -> Result<bool> {
+    /// Runs the v3.4/v3.5 session key exchange. Returns the bytes read past the device's
+    /// response, which belong to the frames that follow and must be read next.
+    async fn negotiate_session_key(
+        &self,
+        stream: &mut TcpStream,
+        seqno: &mut u32,
+    ) -> Result<Vec<u8>> {
         let protocol = get_protocol(self.version(), self.dev_type());
         debug!("Session negotiation (v{})", protocol.version());
 
@@ -1069,33 +3306,51 @@ impl Device {
         )
         .await?;
 
-        // 2. Read response and verify
-        let first_byte = timeout(self.timeout(), stream.read_u8())
-            .await
-            .map_err(|_| TuyaError::Timeout)?
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    TuyaError::KeyOrVersionError
-                } else {
-                    TuyaError::from(e)
-                }
-            })?;
+        // 2. Read response and verify. Chatty devices may report state before answering,
+        // so well-formed frames with other commands are handled like any received frame
+        // and skipped until the response arrives or the timeout elapses.
+        let mut reader = FrameReader::new(&mut *stream);
+        let deadline = Instant::now() + self.timeout();
+        let resp = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let first_byte = timeout(remaining, reader.read_u8())
+                .await
+                .map_err(|_| TuyaError::Timeout)?
+                .map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                        TuyaError::KeyOrVersionError
+                    } else {
+                        TuyaError::from(e)
+                    }
+                })?;
 
-        let resp = self
-            .read_and_parse_from_stream(stream, first_byte)
-            .await?
-            .ok_or(TuyaError::HandshakeFailed)?;
+            let msg = self
+                .read_and_parse_from_stream(&mut reader, first_byte)
+                .await?
+                .ok_or(TuyaError::HandshakeFailed)?;
 
-        if resp.cmd != CommandType::SessKeyNegResp as u32 {
-            return Err(TuyaError::KeyOrVersionError);
-        }
+            if msg.cmd == CommandType::SessKeyNegResp as u32 {
+                break msg;
+            }
+            // A frame that failed to decode is most likely the response under a wrong key.
+            if event_error(&msg).is_some() {
+                return Err(TuyaError::KeyOrVersionError);
+            }
+            debug!(
+                "Skipping cmd=0x{:02X} from {} during session negotiation",
+                msg.cmd, self.id
+            );
+            self.dispatch_message(msg);
+        };
+        let leftover = reader.into_pending();
 
+        let local_key = self.local_key();
         let remote_nonce =
-            protocol.verify_session_key_response(&local_nonce, &resp.payload, &self.local_key)?;
+            protocol.verify_session_key_response(&local_nonce, &resp.payload, &local_key)?;
 
         // 3. Finalize and send SessKeyNegFinish
-        let (session_key, finish_hmac) =
-            protocol.finalize_session_key(&local_nonce, &remote_nonce, &self.local_key)?;
+        let (_, finish_hmac) =
+            protocol.finalize_session_key(&local_nonce, &remote_nonce, &local_key)?;
 
         self.send_raw_to_stream(
             stream,
@@ -1103,12 +3358,16 @@ impl Device {
         )
         .await?;
 
-        // 4. Encrypt and store session key
-        let cipher = TuyaCipher::new(&self.local_key)?;
-        let encrypted_key = protocol.encrypt_session_key(&session_key, &cipher, &local_nonce)?;
+        // 4. Derive and store session key
+        let session_key = crate::crypto::derive_session_key(
+            &local_key,
+            &local_nonce,
+            &remote_nonce,
+            protocol.version(),
+        )?;
 
-        self.with_state_mut(|s| s.session_key = Some(encrypted_key));
-        Ok(true)
+        self.with_state_mut(|s| s.session_key = Some(session_key));
+        Ok(leftover)
     }
 
     async fn resolve_address(&self) -> Result<String> {
@@ -1133,6 +3392,11 @@ impl Device {
             {
                 state.version = v;
             }
+            if let Some(port) = result.port
+                && !state.port_explicit
+            {
+                state.port = port;
+            }
 
             let target_ip = if ip_explicit { config_addr } else { result.ip };
             state.real_ip = target_ip.clone();
@@ -1162,7 +3426,19 @@ impl Device {
         }
         let protocol = get_protocol(version, dev_type);
         let t = self.get_timestamp();
-        protocol.generate_payload(&self.id, command, data, cid, t)
+        let (_, payload) = protocol.generate_payload(&self.id, command, data, cid, t)?;
+        Ok((self.effective_command(&*protocol, command), payload))
+    }
+
+    /// The command ID sent for `command`, honoring `DeviceBuilder::legacy_query`.
+    fn effective_command(&self, protocol: &dyn TuyaProtocol, command: CommandType) -> u32 {
+        if matches!(command, CommandType::DpQuery | CommandType::Control)
+            && protocol.requires_session_key()
+            && self.with_state(|s| s.legacy_query)
+        {
+            return command as u32;
+        }
+        protocol.get_effective_command(command)
     }
 
     async fn process_command<W: AsyncWriteExt + Unpin>(
@@ -1176,8 +3452,17 @@ impl Device {
                 command,
                 data,
                 cid,
-                resp_tx,
+                response_timeout,
+                mut resp_tx,
             } => {
+                if resp_tx.is_closed() {
+                    debug!(
+                        "Request {command:?} for {} was cancelled before sending",
+                        self.id
+                    );
+                    return Ok(());
+                }
+
                 let nowait = self.nowait.load(Ordering::Relaxed);
                 let cmd_code = command as u32;
                 let response_rx = if !nowait && ![3, 4, 5, 9].contains(&cmd_code) {
@@ -1186,6 +3471,8 @@ impl Device {
                     None
                 };
 
+                self.throttle().await;
+
                 let res = self
                     .generate_payload(command, data.clone(), cid.as_deref())
                     .await;
@@ -1204,89 +3491,127 @@ impl Device {
 
                 if let Some(mut rx) = response_rx {
                     let protocol = self.with_state(|s| get_protocol(s.version, s.dev_type));
-                    let effective_cmd = protocol.get_effective_command(command);
-                    let timeout_dur = self.timeout();
+                    let effective_cmd = self.effective_command(&*protocol, command);
+                    let timeout_dur = response_timeout.unwrap_or_else(|| self.request_timeout());
 
-                    let wait_res = timeout(timeout_dur, async {
+                    let wait_fut = timeout(timeout_dur, async {
                         loop {
                             match rx.recv().await {
                                 Ok(msg) => {
-                                    // 0. Check for error response from device (cmd 0)
-                                    if msg.cmd == 0 {
-                                        debug!("Device returned error response (cmd 0), returning as valid response");
+                                    if Self::matches_response(&msg, effective_cmd, cid.as_deref()) {
                                         return Ok(Some(msg));
                                     }
-
-                                    // 1. Check command ID
-                                    let cmd_matches = msg.cmd == effective_cmd
-                                        || msg.cmd == CommandType::Status as u32;
-
-                                    if !cmd_matches {
-                                        continue;
-                                    }
-
-                                    // 1.1 Check if this command requires data (must wait if payload is empty)
-                                    let needs_data = MANDATORY_DATA_CMDS.contains(&msg.cmd);
-
-                                    // Found matching response
-                                    // 2. If we sent a request with a specific CID, verify the response CID matches
-                                    if let Some(ref target_cid) = cid {
-                                        if msg.payload.is_empty() {
-                                            if needs_data {
-                                                trace!("Received empty ACK for command requiring data (0x{:02X}), continuing wait", msg.cmd);
-                                                continue;
-                                            }
-                                            // Empty payload for CID request is considered a valid ACK
-                                            debug!("Received empty ACK for CID request ({}), accepting", target_cid);
-                                            return Ok(Some(msg));
-                                        }
-
-                                        if let Ok(val) = serde_json::from_slice::<Value>(&msg.payload) {
-                                            let resp_cid = val.get("cid").and_then(|c| c.as_str());
-                                            if resp_cid == Some(target_cid) {
-                                                debug!("Received matching response for CID: {}", target_cid);
-                                                return Ok(Some(msg));
-                                            } else {
-                                                // Response for a different CID, ignore and keep waiting
-                                                trace!("Ignoring response for CID: {:?} (expected {})", resp_cid, target_cid);
-                                                continue;
-                                            }
-                                        }
-                                    } else {
-                                        // Request without CID (parent device request)
-                                        if msg.payload.is_empty() {
-                                            if needs_data {
-                                                trace!("Received empty ACK for parent command requiring data (0x{:02X}), continuing wait", msg.cmd);
-                                                continue;
-                                            }
-                                            return Ok(Some(msg));
-                                        }
-
-                                        if let Ok(val) = serde_json::from_slice::<Value>(&msg.payload) {
-                                            if val.get("cid").is_none() {
-                                                return Ok(Some(msg));
-                                            } else {
-                                                // Response with CID for a non-CID request, ignore
-                                                trace!("Ignoring response with CID for parent request");
-                                                continue;
-                                            }
-                                        }
-                                    }
-
-                                    return Ok(Some(msg));
                                 }
-                                Err(_) => return Err(TuyaError::Offline),
+                                Err(RecvError::Lagged(n)) => {
+                                    warn!(
+                                        "Response waiter for {} lagged behind, skipped {n} messages",
+                                        self.id
+                                    );
+                                }
+                                Err(RecvError::Closed) => return Err(TuyaError::Offline),
                             }
                         }
-                    })
-                    .await
-                    .unwrap_or(Err(TuyaError::Timeout));
+                    });
+
+                    // Stop waiting as soon as the caller drops its future, so an abandoned
+                    // request does not hold up the commands queued behind it.
+                    let wait_res = tokio::select! {
+                        res = wait_fut => res.unwrap_or(Err(TuyaError::Timeout)),
+                        () = resp_tx.closed() => {
+                            debug!("Request {command:?} for {} was cancelled while waiting", self.id);
+                            return Ok(());
+                        }
+                    };
 
                     let _ = resp_tx.send(wait_res);
                 } else {
                     let _ = resp_tx.send(Ok(None));
                 }
             }
+            DeviceCommand::Pipeline {
+                requests,
+                mut resp_tx,
+            } => {
+                if resp_tx.is_closed() {
+                    return Ok(());
+                }
+
+                let mut rx = self.broadcast_tx.subscribe();
+                let protocol = self.with_state(|s| get_protocol(s.version, s.dev_type));
+                let mut results: Vec<Option<Result<TuyaMessage>>> =
+                    Vec::with_capacity(requests.len());
+                let mut pending = Vec::new();
+                let mut send_err: Option<TuyaError> = None;
+
+                // Send everything first; replies are collected afterwards.
+                for (i, (command, data, cid)) in requests.into_iter().enumerate() {
+                    if let Some(e) = &send_err {
+                        results.push(Some(Err(e.clone())));
+                        continue;
+                    }
+
+                    self.throttle().await;
+                    let sent = match self.generate_payload(command, data, cid.as_deref()).await {
+                        Ok((cmd_id, payload)) => {
+                            debug!(
+                                "Sending pipelined command: cmd=0x{:02X}, seqno={}",
+                                cmd_id, *seqno
+                            );
+                            self.send_json_msg(stream, seqno, cmd_id, &payload)
+                                .await
+                                .inspect_err(|e| send_err = Some(e.clone()))
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    match sent {
+                        Ok(()) => {
+                            results.push(None);
+                            pending.push((i, self.effective_command(&*protocol, command), cid));
+                        }
+                        Err(e) => results.push(Some(Err(e))),
+                    }
+                }
+
+                // Each reply goes to the earliest pending request it matches.
+                let wait_fut = timeout(self.request_timeout(), async {
+                    while !pending.is_empty() {
+                        match rx.recv().await {
+                            Ok(msg) => {
+                                if let Some(pos) = pending.iter().position(|(_, cmd, cid)| {
+                                    Self::matches_response(&msg, *cmd, cid.as_deref())
+                                }) {
+                                    let (i, _, _) = pending.remove(pos);
+                                    results[i] = Some(Ok(msg));
+                                }
+                            }
+                            Err(RecvError::Lagged(n)) => {
+                                warn!(
+                                    "Pipeline waiter for {} lagged behind, skipped {n} messages",
+                                    self.id
+                                );
+                            }
+                            Err(RecvError::Closed) => return TuyaError::Offline,
+                        }
+                    }
+                    TuyaError::Timeout
+                });
+
+                let unanswered = tokio::select! {
+                    res = wait_fut => res.unwrap_or(TuyaError::Timeout),
+                    () = resp_tx.closed() => {
+                        debug!("Pipeline for {} was cancelled while waiting", self.id);
+                        return Ok(());
+                    }
+                };
+
+                let _ = resp_tx.send(
+                    results
+                        .into_iter()
+                        .map(|r| r.unwrap_or_else(|| Err(unanswered.clone())))
+                        .collect(),
+                );
+            }
             DeviceCommand::Disconnect => {
                 debug!("Disconnect command received for device {}", self.id);
                 return Err(TuyaError::Offline);
@@ -1297,47 +3622,258 @@ impl Device {
                     self.id
                 );
             }
+            cmd @ DeviceCommand::Flush(_) => cmd.respond(Ok(None)),
         }
         Ok(())
     }
 
-    async fn process_socket_data<R: AsyncReadExt + Unpin>(
+    /// Checks whether `msg` is the reply to a request for `effective_cmd` (and `cid`, if set).
+    fn matches_response(msg: &TuyaMessage, effective_cmd: u32, cid: Option<&str>) -> bool {
+        // 0. Check for error response from device (cmd 0)
+        if msg.cmd == 0 {
+            debug!("Device returned error response (cmd 0), returning as valid response");
+            return true;
+        }
+
+        // 1. Check command ID
+        let cmd_matches = msg.cmd == effective_cmd || msg.cmd == CommandType::Status as u32;
+
+        if !cmd_matches {
+            return false;
+        }
+
+        // 1.1 Check if this command requires data (must wait if payload is empty)
+        let needs_data = MANDATORY_DATA_CMDS.contains(&msg.cmd);
+
+        // Found matching response
+        // 2. If we sent a request with a specific CID, verify the response CID matches
+        if let Some(target_cid) = cid {
+            if msg.payload.is_empty() {
+                if needs_data {
+                    trace!(
+                        "Received empty ACK for command requiring data (0x{:02X}), continuing wait",
+                        msg.cmd
+                    );
+                    return false;
+                }
+                // Empty payload for CID request is considered a valid ACK
+                debug!(
+                    "Received empty ACK for CID request ({}), accepting",
+                    target_cid
+                );
+                return true;
+            }
+
+            if let Ok(val) = serde_json::from_slice::<Value>(&msg.payload) {
+                // v3.4+ gateways nest the CID under `data`
+                let resp_cid = val
+                    .get("cid")
+                    .or_else(|| val.get("data").and_then(|d| d.get("cid")))
+                    .and_then(|c| c.as_str());
+                if resp_cid == Some(target_cid) {
+                    debug!("Received matching response for CID: {}", target_cid);
+                    return true;
+                } else {
+                    // Response for a different CID, ignore and keep waiting
+                    trace!(
+                        "Ignoring response for CID: {:?} (expected {})",
+                        resp_cid, target_cid
+                    );
+                    return false;
+                }
+            }
+        } else {
+            // Request without CID (parent device request)
+            if msg.payload.is_empty() {
+                if needs_data {
+                    trace!(
+                        "Received empty ACK for parent command requiring data (0x{:02X}), continuing wait",
+                        msg.cmd
+                    );
+                    return false;
+                }
+                return true;
+            }
+
+            if let Ok(val) = serde_json::from_slice::<Value>(&msg.payload) {
+                if val.get("cid").is_none() {
+                    return true;
+                } else {
+                    // Response with CID for a non-CID request, ignore
+                    trace!("Ignoring response with CID for parent request");
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Handles commands in dry-run mode: packets are built and published, never sent.
+    async fn run_dry_run(&self, mut rx: mpsc::Receiver<DeviceCommand>) {
+        info!(
+            "Device {} is in dry-run mode, no packets will be sent",
+            self.id
+        );
+        let mut seqno = 1u32;
+        while let Some(cmd) = rx.recv().await {
+            match cmd {
+                DeviceCommand::Request {
+                    command,
+                    data,
+                    cid,
+                    resp_tx,
+                    ..
+                } => {
+                    let res = self
+                        .build_dry_run_packet(&mut seqno, command, data, cid.as_deref())
+                        .await
+                        .map(|_| None);
+                    let _ = resp_tx.send(res);
+                }
+                DeviceCommand::Pipeline { requests, resp_tx } => {
+                    let mut results = Vec::with_capacity(requests.len());
+                    for (command, data, cid) in requests {
+                        results.push(
+                            self.build_dry_run_packet(&mut seqno, command, data, cid.as_deref())
+                                .await,
+                        );
+                    }
+                    let _ = resp_tx.send(results);
+                }
+                cmd @ DeviceCommand::Flush(_) => cmd.respond(Ok(None)),
+                DeviceCommand::Disconnect | DeviceCommand::ConnectNow => {}
+            }
+        }
+    }
+
+    /// Builds and publishes the packet for a command, returning an empty ACK-like message.
+    async fn build_dry_run_packet(
         &self,
-        stream: &mut R,
+        seqno: &mut u32,
+        command: CommandType,
+        data: Option<Value>,
+        cid: Option<&str>,
+    ) -> Result<TuyaMessage> {
+        let (cmd_id, payload) = self.generate_payload(command, data, cid).await?;
+        let payload_bytes = serde_json::to_vec(&payload).unwrap_or_default();
+        let msg = self.build_message(seqno, cmd_id, payload_bytes);
+        let packed = self.pack_msg(msg.clone())?;
+        debug!(
+            "Dry run: not sending cmd=0x{:02X}, seqno={} ({} bytes)",
+            msg.cmd,
+            msg.seqno,
+            packed.len()
+        );
+        self.emit_outbound(&msg, &packed);
+        Ok(TuyaMessage {
+            seqno: msg.seqno,
+            cmd: msg.cmd,
+            ..Default::default()
+        })
+    }
+
+    fn tap_wire(&self, dir: Direction, bytes: &[u8]) {
+        if let Some(tap) = self.with_state(|s| s.wire_tap.clone()) {
+            tap(dir, bytes);
+        }
+    }
+
+    fn emit_outbound(&self, msg: &TuyaMessage, packed: &[u8]) {
+        if self.outbound_tx.receiver_count() > 0 {
+            let _ = self.outbound_tx.send(OutboundPacket {
+                seqno: msg.seqno,
+                cmd: msg.cmd,
+                payload: msg.payload.clone(),
+                packet: packed.to_vec(),
+            });
+        }
+    }
+
+    /// Delays the next packet until `min_command_interval` has passed since the last one.
+    async fn throttle(&self) {
+        let wait =
+            self.with_state(|s| s.min_command_interval.saturating_sub(s.last_sent.elapsed()));
+        if !wait.is_zero() {
+            trace!("Throttling command for {} by {:?}", self.id, wait);
+            sleep(wait).await;
+        }
+    }
+
+    async fn process_socket_data<R: AsyncRead + Unpin>(
+        &self,
+        stream: &mut FrameReader<R>,
         first_byte: u8,
     ) -> Result<()> {
         if let Some(msg) = self.read_and_parse_from_stream(stream, first_byte).await? {
             self.update_last_received();
             self.reset_failure_count();
+            self.dispatch_message(msg);
+        }
+        Ok(())
+    }
+
+    /// Hands a received message to listeners and the status report stream.
+    fn dispatch_message(&self, msg: TuyaMessage) {
+        debug!(
+            "Received message: cmd=0x{:02X}, payload_len={}",
+            msg.cmd,
+            msg.payload.len()
+        );
+        if msg.payload.is_empty() {
             debug!(
-                "Received message: cmd=0x{:02X}, payload_len={}",
-                msg.cmd,
-                msg.payload.len()
+                "Received empty payload message (cmd 0x{:02X}), broadcasting as ACK",
+                msg.cmd
             );
-            if msg.payload.is_empty() {
-                debug!(
-                    "Received empty payload message (cmd 0x{:02X}), broadcasting as ACK",
-                    msg.cmd
+            self.publish(msg);
+        } else {
+            // Check if payload is valid JSON
+            if serde_json::from_slice::<Value>(&msg.payload).is_err() {
+                debug!("Non-JSON payload detected, broadcasting as ERR_JSON");
+                let payload_hex = hex::encode(&msg.payload);
+                self.broadcast_error(
+                    ERR_JSON,
+                    Some(serde_json::json!({
+                        keys::PAYLOAD_RAW: payload_hex,
+                        "cmd": msg.cmd
+                    })),
                 );
-                let _ = self.broadcast_tx.send(msg);
             } else {
-                // Check if payload is valid JSON
-                if serde_json::from_slice::<Value>(&msg.payload).is_err() {
-                    debug!("Non-JSON payload detected, broadcasting as ERR_JSON");
-                    let payload_hex = hex::encode(&msg.payload);
-                    self.broadcast_error(
-                        ERR_JSON,
-                        Some(serde_json::json!({
-                            keys::PAYLOAD_RAW: payload_hex,
-                            "cmd": msg.cmd
-                        })),
-                    );
-                } else {
-                    let _ = self.broadcast_tx.send(msg);
-                }
+                self.emit_status_report(&msg);
+                self.publish(msg);
             }
         }
-        Ok(())
+    }
+
+    fn emit_status_report(&self, msg: &TuyaMessage) {
+        if msg.cmd != CommandType::Status as u32 || self.report_tx.receiver_count() == 0 {
+            return;
+        }
+        let Ok(val) = serde_json::from_slice::<Value>(&msg.payload) else {
+            return;
+        };
+        let Some(dps) = val
+            .get("dps")
+            .or_else(|| val.get("data").and_then(|d| d.get("dps")))
+            .filter(|d| d.is_object())
+            .cloned()
+        else {
+            return;
+        };
+        let cid = val
+            .get("cid")
+            .or_else(|| val.get("data").and_then(|d| d.get("cid")))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let after_command = self.with_state(|s| {
+            s.last_command
+                .is_some_and(|t| t.elapsed() < STATUS_REPORT_ECHO_WINDOW)
+        });
+        let _ = self.report_tx.send(StatusReport {
+            dps,
+            cid,
+            after_command,
+        });
     }
 
     async fn process_heartbeat<W: AsyncWriteExt + Unpin>(
@@ -1358,6 +3894,59 @@ impl Device {
     }
 }
 
+/// Socket reader that can put bytes back, so a malformed frame does not take the start of
+/// the next one with it.
+struct FrameReader<R> {
+    inner: BufReader<R>,
+    pending: Vec<u8>,
+}
+
+impl<R: AsyncRead> FrameReader<R> {
+    /// Wraps `inner` in a `BufReader`, so the byte-wise prefix scan does not cost one read
+    /// call per byte.
+    fn new(inner: R) -> Self {
+        Self::with_pending(inner, Vec::new())
+    }
+
+    /// Starts with `pending` queued ahead of the socket, e.g. bytes left over from the
+    /// handshake reader.
+    fn with_pending(inner: R, pending: Vec<u8>) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            pending,
+        }
+    }
+
+    /// Returns the bytes taken off the socket but not read yet, including what is still
+    /// buffered.
+    fn into_pending(self) -> Vec<u8> {
+        let mut pending = self.pending;
+        pending.extend_from_slice(self.inner.buffer());
+        pending
+    }
+
+    /// Queues `bytes` to be read again before any new data from the socket.
+    fn unread(&mut self, bytes: &[u8]) {
+        self.pending.splice(0..0, bytes.iter().copied());
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FrameReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.pending.is_empty() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let n = self.pending.len().min(buf.remaining());
+        buf.put_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
 impl Device {
     // -------------------------------------------------------------------------
     // Low-level Message Framing & Encryption
@@ -1391,11 +3980,18 @@ impl Device {
     }
 
     fn pack_msg(&self, mut msg: TuyaMessage) -> Result<Vec<u8>> {
-        let (version, dev_type) = self.with_state(|s| (s.version, s.dev_type));
+        let (version, dev_type, control_base64) =
+            self.with_state(|s| (s.version, s.dev_type, s.control_base64));
         let cipher = self.get_cipher()?;
         let protocol = get_protocol(version, dev_type);
 
-        msg.payload = protocol.pack_payload(&msg.payload, msg.cmd, &cipher)?;
+        let is_control =
+            msg.cmd == CommandType::Control as u32 || msg.cmd == CommandType::ControlNew as u32;
+        msg.payload = if control_base64 && version == Version::V3_3 && is_control {
+            ProtocolV33.pack_control_base64(&msg.payload, &cipher)?
+        } else {
+            protocol.pack_payload(&msg.payload, msg.cmd, &cipher)?
+        };
         msg.prefix = protocol.get_prefix();
 
         let hmac_key = protocol.get_hmac_key(cipher.key());
@@ -1411,7 +4007,11 @@ impl Device {
     ) -> Result<()> {
         let payload_bytes = serde_json::to_vec(payload).unwrap_or_default();
         let msg = self.build_message(seqno, cmd, payload_bytes);
-        self.send_raw_to_stream(stream, msg).await
+        self.send_raw_to_stream(stream, msg).await?;
+        if cmd != CommandType::HeartBeat as u32 {
+            self.with_state_mut(|s| s.last_command = Some(Instant::now()));
+        }
+        Ok(())
     }
 
     async fn send_raw_to_stream<W: AsyncWriteExt + Unpin>(
@@ -1419,24 +4019,40 @@ impl Device {
         stream: &mut W,
         msg: TuyaMessage,
     ) -> Result<()> {
+        let plain = (self.outbound_tx.receiver_count() > 0).then(|| msg.clone());
         let packed = self.pack_msg(msg)?;
+        if let Some(plain) = plain {
+            self.emit_outbound(&plain, &packed);
+        }
+        self.tap_wire(Direction::Outbound, &packed);
         timeout(self.timeout(), stream.write_all(&packed))
             .await
             .map_err(|_| TuyaError::Timeout)?
             .map_err(TuyaError::from)?;
 
         self.update_last_sent();
+        self.with_state_mut(|s| {
+            s.metrics.packets_sent += 1;
+            s.metrics.bytes_sent += packed.len() as u64;
+        });
         Ok(())
     }
 
-    async fn read_and_parse_from_stream<R: AsyncReadExt + Unpin>(
+    async fn read_and_parse_from_stream<R: AsyncRead + Unpin>(
         &self,
-        stream: &mut R,
+        stream: &mut FrameReader<R>,
         first_byte: u8,
     ) -> Result<Option<TuyaMessage>> {
-        let prefix = match self.scan_for_prefix(stream, first_byte).await? {
-            Some(p) => p,
-            None => return Ok(None),
+        let prefix = match self.scan_for_prefix(stream, first_byte).await {
+            Ok(p) => p,
+            Err(e @ TuyaError::DecodeError(_)) => {
+                warn!("Lost packet framing from {}: {}", self.id, e);
+                return Ok(Some(self.error_helper(
+                    ERR_PAYLOAD,
+                    Some(serde_json::json!(e.to_string())),
+                )));
+            }
+            Err(e) => return Err(e),
         };
 
         // Read remaining 12 bytes of header (16 bytes total)
@@ -1451,17 +4067,16 @@ impl Device {
         let dev_type_before = self.dev_type();
         match self.parse_and_read_body(stream, header_buf).await {
             Ok(Some(msg)) => {
+                // Empty frames (heartbeat and v3.5 ACKs) never cause the switch, so they are not
+                // reported as the transition even if the type changed meanwhile.
                 if dev_type_before != DeviceType::Device22
                     && self.dev_type() == DeviceType::Device22
+                    && !msg.payload.is_empty()
                 {
                     debug!("Device22 transition detected, reporting with original payload");
-                    let original_payload = if msg.payload.is_empty() {
-                        Value::Null
-                    } else {
-                        serde_json::from_slice(&msg.payload).unwrap_or_else(
-                            |_| serde_json::json!({ keys::PAYLOAD_RAW: hex::encode(&msg.payload) }),
-                        )
-                    };
+                    let original_payload = serde_json::from_slice(&msg.payload).unwrap_or_else(
+                        |_| serde_json::json!({ keys::PAYLOAD_RAW: hex::encode(&msg.payload) }),
+                    );
                     return Ok(Some(self.error_helper(ERR_DEVTYPE, Some(original_payload))));
                 }
                 Ok(Some(msg))
@@ -1484,43 +4099,32 @@ impl Device {
         &self,
         stream: &mut R,
         first_byte: u8,
-    ) -> Result<Option<[u8; 4]>> {
-        let mut current_prefix = first_byte as u32;
-
-        for _ in 0..3 {
-            let next_byte = timeout(self.timeout(), stream.read_u8())
-                .await
-                .map_err(|_| TuyaError::Timeout)?
-                .map_err(TuyaError::from)?;
-            current_prefix = (current_prefix << 8) | (next_byte as u32);
-        }
-
-        for _ in 0..1024 {
-            if current_prefix == PREFIX_55AA || current_prefix == PREFIX_6699 {
-                return Ok(Some(current_prefix.to_be_bytes()));
-            }
-
-            let next_byte = timeout(self.timeout(), stream.read_u8())
-                .await
-                .map_err(|_| TuyaError::Timeout)?
-                .map_err(TuyaError::from)?;
-            current_prefix = (current_prefix << 8) | (next_byte as u32);
-        }
-        Ok(None)
+    ) -> Result<[u8; 4]> {
+        let first = [first_byte];
+        let mut reader = (&first[..]).chain(&mut *stream);
+        let max_skip = self.with_state(|s| s.max_prefix_scan);
+        timeout(self.timeout(), read_prefix(&mut reader, max_skip))
+            .await
+            .map_err(|_| TuyaError::Timeout)?
     }
 
-    async fn parse_and_read_body<R: AsyncReadExt + Unpin>(
+    async fn parse_and_read_body<R: AsyncRead + Unpin>(
         &self,
-        stream: &mut R,
+        stream: &mut FrameReader<R>,
         header_buf: [u8; 16],
     ) -> Result<Option<TuyaMessage>> {
         let (packet, header) = self.read_full_packet(stream, header_buf).await?;
-        trace!("Received packet (hex): {:?}", hex::encode(&packet));
+        trace!("Received packet: {} bytes", packet.len());
+        self.with_state_mut(|s| {
+            s.metrics.packets_received += 1;
+            s.metrics.bytes_received += packet.len() as u64;
+        });
+        trace_payload!("Received packet (hex): {:?}", hex::encode(&packet));
 
         let mut decoded = self.unpack_and_check_dev22(&packet, header).await?;
 
         if !decoded.payload.is_empty() {
-            trace!("Raw payload (hex): {:?}", hex::encode(&decoded.payload));
+            trace_payload!("Raw payload (hex): {:?}", hex::encode(&decoded.payload));
             decoded.payload = self
                 .decrypt_and_clean_payload(decoded.payload, decoded.prefix)
                 .await?;
@@ -1529,15 +4133,15 @@ impl Device {
         Ok(Some(decoded))
     }
 
-    async fn read_full_packet<R: AsyncReadExt + Unpin>(
+    async fn read_full_packet<R: AsyncRead + Unpin>(
         &self,
-        stream: &mut R,
+        stream: &mut FrameReader<R>,
         header_buf: [u8; 16],
     ) -> Result<(Vec<u8>, TuyaHeader)> {
         let prefix =
             u32::from_be_bytes([header_buf[0], header_buf[1], header_buf[2], header_buf[3]]);
 
-        let (header, mut packet) = if prefix == PREFIX_6699 {
+        let mut packet = if prefix == PREFIX_6699 {
             let mut extra = [0u8; 2];
             timeout(self.timeout(), stream.read_exact(&mut extra))
                 .await
@@ -1547,9 +4151,22 @@ impl Device {
             let mut fh = Vec::with_capacity(18);
             fh.extend_from_slice(&header_buf);
             fh.extend_from_slice(&extra);
-            (parse_header(&fh)?, fh)
+            fh
         } else {
-            (parse_header(&header_buf)?, header_buf.to_vec())
+            header_buf.to_vec()
+        };
+        let max_frame_size = self.with_state(|s| s.max_frame_size);
+        let header = match parse_header_with_limit(&packet, max_frame_size) {
+            Ok(h) => h,
+            Err(e) => {
+                self.resync(stream, &packet[4..]);
+                return Err(match e {
+                    TuyaError::InvalidPayload => TuyaError::DecodeError(format!(
+                        "Frame exceeds max_frame_size ({max_frame_size} bytes)"
+                    )),
+                    e => e,
+                });
+            }
         };
 
         let total_len = header.total_length as usize;
@@ -1560,10 +4177,41 @@ impl Device {
             .await
             .map_err(|_| TuyaError::Timeout)?
             .map_err(TuyaError::from)?;
+        self.tap_wire(Direction::Inbound, &packet);
+
+        let suffix = if prefix == PREFIX_6699 {
+            SUFFIX_6699
+        } else {
+            SUFFIX_55AA
+        };
+        if packet[total_len - 4..] != suffix.to_be_bytes() && self.resync(stream, &packet[4..]) {
+            return Err(TuyaError::DecodeError("Frame suffix missing".into()));
+        }
 
         Ok((packet, header))
     }
 
+    /// Puts back the bytes of a malformed frame from the next packet prefix on, so they are
+    /// parsed again instead of being lost with it. Returns `false` when resync is disabled.
+    fn resync<R: AsyncRead + Unpin>(&self, stream: &mut FrameReader<R>, bytes: &[u8]) -> bool {
+        if !self.with_state(|s| s.resync_frames) {
+            return false;
+        }
+        let resume = bytes
+            .windows(4)
+            .position(|w| w == PREFIX_55AA.to_be_bytes() || w == PREFIX_6699.to_be_bytes())
+            // Keep a possibly truncated prefix at the end
+            .unwrap_or(bytes.len().saturating_sub(3));
+        warn!(
+            "Malformed frame from {}, discarding {} bytes and resynchronizing",
+            self.id,
+            resume + 4
+        );
+        stream.unread(&bytes[resume..]);
+        self.with_state_mut(|s| s.metrics.resynced_frames += 1);
+        true
+    }
+
     async fn unpack_and_check_dev22(
         &self,
         packet: &[u8],
@@ -1573,23 +4221,29 @@ impl Device {
         let protocol = get_protocol(version, dev_type);
         let cipher = self.get_cipher()?;
         let hmac_key = protocol.get_hmac_key(cipher.key());
-
-        unpack_message(packet, hmac_key, Some(header.clone()), Some(false)).or_else(|e| {
-            // Only allow switching if dev_type is Auto and protocol allows it
-            if protocol.should_check_dev22_fallback()
-                && dev_type == DeviceType::Auto
-                && let Ok(d) = unpack_message(packet, None, Some(header), Some(false))
-            {
-                info!("Device22 detected via CRC32 fallback. Switching mode.");
-                self.set_dev_type(DeviceType::Device22);
-                return Ok(d);
-            }
-            Err(e)
-        })
+        let (verify, auto_device22) = self.with_state(|s| (s.verify_checksum, s.auto_device22));
+
+        unpack_message_with_verify(packet, hmac_key, Some(header.clone()), Some(false), verify)
+            .or_else(|e| {
+                // Only allow switching if dev_type is Auto and protocol allows it. An empty
+                // frame carries no evidence of device22 and must never trigger the switch.
+                if protocol.should_check_dev22_fallback()
+                    && dev_type == DeviceType::Auto
+                    && auto_device22
+                    && let Ok(d) = unpack_message(packet, None, Some(header), Some(false))
+                    && !d.payload.is_empty()
+                {
+                    info!("Device22 detected via CRC32 fallback. Switching mode.");
+                    self.set_dev_type(DeviceType::Device22);
+                    return Ok(d);
+                }
+                Err(e)
+            })
     }
 
     async fn decrypt_and_clean_payload(&self, payload: Vec<u8>, _prefix: u32) -> Result<Vec<u8>> {
-        let (version, mut dev_type) = self.with_state(|s| (s.version, s.dev_type));
+        let (version, mut dev_type, auto_device22) =
+            self.with_state(|s| (s.version, s.dev_type, s.auto_device22));
         let original_dev_type = dev_type;
         if dev_type == DeviceType::Auto {
             dev_type = DeviceType::Default;
@@ -1602,6 +4256,7 @@ impl Device {
 
         if protocol.should_check_dev22_fallback()
             && original_dev_type == DeviceType::Auto
+            && auto_device22
             && String::from_utf8_lossy(&decrypted).contains(DATA_UNVALID)
         {
             warn!("Device22 detected via '{DATA_UNVALID}' payload. Switching mode.");
@@ -1615,7 +4270,7 @@ impl Device {
         let mut state = self.state.write();
 
         // Determine which key to use: session_key if available, otherwise local_key
-        let key = state.session_key.as_deref().unwrap_or(&self.local_key);
+        let key = state.session_key.as_deref().unwrap_or(&state.local_key);
 
         if let Some(ref cipher) = state.cipher
             && cipher.key() == key
@@ -1650,34 +4305,65 @@ impl Device {
     }
 
     fn error_helper(&self, code: u32, payload: Option<Value>) -> TuyaMessage {
-        let mut response = serde_json::json!({
-            keys::ERR_MSG: get_error_message(code),
-            keys::ERR_CODE: code,
-        });
+        error_message(code, payload, self.event_prefix())
+    }
 
-        if let Some(p) = payload {
-            match p {
-                Value::String(s) => response[keys::PAYLOAD_STR] = Value::String(s),
-                Value::Object(mut obj) => {
-                    if let Some(raw) = obj
-                        .remove("data")
-                        .or_else(|| obj.remove("payload"))
-                        .or_else(|| obj.remove(keys::PAYLOAD_RAW))
-                    {
-                        response[keys::PAYLOAD_RAW] = raw;
-                    }
-                    if let Some(res_obj) = response.as_object_mut() {
-                        res_obj.extend(obj);
-                    }
+    pub(crate) fn event_prefix(&self) -> u32 {
+        get_protocol(self.version(), self.dev_type()).get_prefix()
+    }
+}
+
+/// Event yielded to a listener that fell behind the broadcast buffer and missed messages.
+pub(crate) fn lagged_message(skipped: u64, prefix: u32) -> TuyaMessage {
+    error_message(
+        ERR_STATE,
+        Some(serde_json::json!({ "skipped": skipped })),
+        prefix,
+    )
+}
+
+/// Decodes an error event built by `error_message` back into a `TuyaError`.
+fn event_error(msg: &TuyaMessage) -> Option<TuyaError> {
+    if msg.cmd != 0 || msg.payload.is_empty() {
+        return None;
+    }
+    let val: Value = serde_json::from_slice(&msg.payload).ok()?;
+    let code = u32::try_from(val.get(keys::ERR_CODE)?.as_u64()?).ok()?;
+    match code {
+        ERR_SUCCESS | ERR_DEVTYPE => None,
+        _ => Some(TuyaError::from_code(code)),
+    }
+}
+
+/// Builds a synthetic error event in the same JSON shape the device task broadcasts.
+fn error_message(code: u32, payload: Option<Value>, prefix: u32) -> TuyaMessage {
+    let mut response = serde_json::json!({
+        keys::ERR_MSG: get_error_message(code),
+        keys::ERR_CODE: code,
+    });
+
+    if let Some(p) = payload {
+        match p {
+            Value::String(s) => response[keys::PAYLOAD_STR] = Value::String(s),
+            Value::Object(mut obj) => {
+                if let Some(raw) = obj
+                    .remove("data")
+                    .or_else(|| obj.remove("payload"))
+                    .or_else(|| obj.remove(keys::PAYLOAD_RAW))
+                {
+                    response[keys::PAYLOAD_RAW] = raw;
+                }
+                if let Some(res_obj) = response.as_object_mut() {
+                    res_obj.extend(obj);
                 }
-                _ => response[keys::ERR_PAYLOAD_OBJ] = p,
             }
+            _ => response[keys::ERR_PAYLOAD_OBJ] = p,
         }
+    }
 
-        TuyaMessage {
-            payload: serde_json::to_vec(&response).unwrap_or_default(),
-            prefix: get_protocol(self.version(), self.dev_type()).get_prefix(),
-            ..Default::default()
-        }
+    TuyaMessage {
+        payload: serde_json::to_vec(&response).unwrap_or_default(),
+        prefix,
+        ..Default::default()
     }
 }
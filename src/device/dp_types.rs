@@ -0,0 +1,124 @@
+//! Typed interpretation of DP values.
+//!
+//! Devices report every DP as a plain JSON value. These helpers decode one according to the
+//! type declared in the device's DP schema. Opt-in: nothing in the raw device API uses them.
+
+use crate::error::{Result, TuyaError};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Declared type of a DP, following the Tuya DP schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DpType {
+    /// `true`/`false` switch
+    Bool,
+    /// Signed integer
+    Int,
+    /// Integer with `scale` implied decimal places, as in the schema's `"scale"` field:
+    /// a raw 235 with `scale: 1` is 23.5
+    ScaledInt { scale: u32 },
+    /// One of a fixed set of strings. Leave `range` empty to accept any value.
+    Enum { range: Vec<String> },
+    /// Free-form string
+    String,
+    /// Fault flags packed into an integer; bit `n` is named `labels[n]`
+    Bitmap { labels: Vec<String> },
+}
+
+/// A DP value decoded by [`decode_dp`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum DpValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Enum(String),
+    String(String),
+    /// Labels of the bits that are set, `bitN` for bits without a label
+    Bitmap(Vec<String>),
+}
+
+impl DpValue {
+    /// Returns the value as a number, for `Int` and `Float`.
+    #[must_use]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(n) => Some(*n as f64),
+            Self::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a raw DP value according to its declared type.
+///
+/// Integers may arrive as JSON numbers or numeric strings. Values above `i32::MAX` that fit
+/// in 32 bits are read as two's complement, since some firmware sends negative readings
+/// (e.g. sub-zero temperatures) as unsigned. Fails with `InvalidPayload` if the value does
+/// not fit the type.
+///
+/// ```
+/// use rustuya::device::dp_types::{DpType, DpValue, decode_dp};
+/// use serde_json::json;
+///
+/// let temp = decode_dp(&json!(4294967271u64), &DpType::ScaledInt { scale: 1 }).unwrap();
+/// assert_eq!(temp.as_f64(), Some(-2.5));
+///
+/// let mode = DpType::Enum { range: vec!["auto".into(), "manual".into()] };
+/// assert_eq!(decode_dp(&json!("auto"), &mode).unwrap(), DpValue::Enum("auto".into()));
+/// assert!(decode_dp(&json!("turbo"), &mode).is_err());
+///
+/// let faults = DpType::Bitmap { labels: vec!["overheat".into(), "low_battery".into()] };
+/// assert_eq!(
+///     decode_dp(&json!(6), &faults).unwrap(),
+///     DpValue::Bitmap(vec!["low_battery".into(), "bit2".into()])
+/// );
+/// ```
+pub fn decode_dp(value: &Value, ty: &DpType) -> Result<DpValue> {
+    let decoded = match ty {
+        DpType::Bool => match value {
+            Value::Bool(b) => Some(DpValue::Bool(*b)),
+            _ => None,
+        },
+        DpType::Int => as_signed(value).map(DpValue::Int),
+        DpType::ScaledInt { scale } => {
+            as_signed(value).map(|n| DpValue::Float(n as f64 / 10f64.powi(*scale as i32)))
+        }
+        DpType::Enum { range } => value
+            .as_str()
+            .filter(|s| range.is_empty() || range.iter().any(|r| r == s))
+            .map(|s| DpValue::Enum(s.to_string())),
+        DpType::String => value.as_str().map(|s| DpValue::String(s.to_string())),
+        DpType::Bitmap { labels } => as_unsigned(value).map(|bits| {
+            DpValue::Bitmap(
+                (0..64)
+                    .filter(|n| bits & (1 << n) != 0)
+                    .map(|n| labels.get(n).cloned().unwrap_or_else(|| format!("bit{n}")))
+                    .collect(),
+            )
+        }),
+    };
+    decoded.ok_or(TuyaError::InvalidPayload)
+}
+
+fn as_unsigned(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_signed(value: &Value) -> Option<i64> {
+    let n = match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_u64().map(|u| u as i64))?,
+        Value::String(s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    // 32-bit two's complement sent as unsigned
+    if n > i64::from(i32::MAX) && n <= i64::from(u32::MAX) {
+        Some(i64::from(n as u32 as i32))
+    } else {
+        Some(n)
+    }
+}
@@ -0,0 +1,115 @@
+//! Energy-monitoring readings.
+//!
+//! Converts the raw voltage, current, and power DPs reported by metering smart plugs into
+//! volts, amps, and watts.
+
+use crate::device::Device;
+use crate::error::{Result, TuyaError};
+use crate::protocol::TuyaMessage;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A DP index and the divisor that converts its raw integer value to the physical unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledDp {
+    /// DP index, e.g. 20
+    pub dp: u32,
+    /// Raw value divided by this gives the unit, e.g. 10.0 for a DP in 0.1 V
+    pub divisor: f64,
+}
+
+impl ScaledDp {
+    #[must_use]
+    pub fn new(dp: u32, divisor: f64) -> Self {
+        Self { dp, divisor }
+    }
+}
+
+/// Where a device reports its energy DPs.
+///
+/// The default matches the most common plug layout: current on DP 18 (mA), power on
+/// DP 19 (0.1 W), and voltage on DP 20 (0.1 V).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyMapping {
+    /// Voltage DP, converted to volts
+    pub voltage: ScaledDp,
+    /// Current DP, converted to amps
+    pub current: ScaledDp,
+    /// Power DP, converted to watts
+    pub power: ScaledDp,
+}
+
+impl Default for EnergyMapping {
+    fn default() -> Self {
+        Self {
+            voltage: ScaledDp::new(20, 10.0),
+            current: ScaledDp::new(18, 1000.0),
+            power: ScaledDp::new(19, 10.0),
+        }
+    }
+}
+
+/// Voltage, current, and power in physical units. Fields are `None` when the device did
+/// not include the DP (devices often report only the values that changed).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct EnergyReading {
+    /// Voltage in volts
+    pub voltage_v: Option<f64>,
+    /// Current in amps
+    pub current_a: Option<f64>,
+    /// Power in watts
+    pub power_w: Option<f64>,
+}
+
+impl EnergyReading {
+    /// Reads the mapped DPs from a DPS object. Values may be numbers or numeric strings.
+    /// Returns `None` if none of the mapped DPs are present.
+    ///
+    /// ```
+    /// use rustuya::energy::{EnergyMapping, EnergyReading};
+    ///
+    /// let dps = serde_json::json!({"1": true, "18": 512, "19": "1180", "20": 2301});
+    /// let reading = EnergyReading::from_dps(dps.as_object().unwrap(), &EnergyMapping::default())
+    ///     .unwrap();
+    /// assert_eq!(reading.voltage_v, Some(230.1));
+    /// assert_eq!(reading.current_a, Some(0.512));
+    /// assert_eq!(reading.power_w, Some(118.0));
+    /// ```
+    #[must_use]
+    pub fn from_dps(dps: &Map<String, Value>, mapping: &EnergyMapping) -> Option<Self> {
+        let read = |scaled: ScaledDp| {
+            let raw = match dps.get(&scaled.dp.to_string())? {
+                Value::Number(n) => n.as_f64()?,
+                Value::String(s) => s.trim().parse().ok()?,
+                _ => return None,
+            };
+            Some(raw / scaled.divisor)
+        };
+
+        let reading = Self {
+            voltage_v: read(mapping.voltage),
+            current_a: read(mapping.current),
+            power_w: read(mapping.power),
+        };
+        (reading != Self::default()).then_some(reading)
+    }
+
+    /// Reads the mapped DPs from a status reply or a `listener()` message.
+    #[must_use]
+    pub fn from_message(msg: &TuyaMessage, mapping: &EnergyMapping) -> Option<Self> {
+        Self::from_dps(&msg.dps()?, mapping)
+    }
+}
+
+/// Queries the device status and returns its energy reading.
+///
+/// Fails with `InvalidPayload` if the reply carries none of the mapped DPs, and always in
+/// nowait mode, where `status()` returns no reply.
+pub async fn read(device: &Device, mapping: &EnergyMapping) -> Result<EnergyReading> {
+    let payload = device.status().await?.ok_or(TuyaError::InvalidPayload)?;
+    let msg = TuyaMessage {
+        payload: payload.into_bytes(),
+        ..Default::default()
+    };
+    EnergyReading::from_message(&msg, mapping).ok_or(TuyaError::InvalidPayload)
+}
@@ -48,6 +48,16 @@ pub enum TuyaError {
     #[error("Check device key or version (Error 914)")]
     KeyOrVersionError,
 
+    /// The device accepted the TCP connection but rejected the handshake or closed it
+    /// before the first reply, which almost always means the local key is wrong. Only
+    /// reported when the version is known (set explicitly or from discovery); otherwise
+    /// these failures are reported as [`TuyaError::KeyOrVersionError`].
+    #[error("Device reachable but rejected the local key (Error 915)")]
+    AuthRejected,
+
+    #[error("Function not supported by device")]
+    FunctionNotSupported,
+
     #[error("Device ID '{0}' not found")]
     DeviceNotFound(String),
 }
@@ -83,6 +93,8 @@ impl TuyaError {
             TuyaError::Offline => ERR_OFFLINE,
             TuyaError::HandshakeFailed => ERR_KEY_OR_VER,
             TuyaError::KeyOrVersionError => ERR_KEY_OR_VER,
+            TuyaError::AuthRejected => ERR_AUTH_REJECTED,
+            TuyaError::FunctionNotSupported => ERR_FUNCTION,
             TuyaError::DeviceNotFound(_) => ERR_JSON,
             TuyaError::Timeout => ERR_TIMEOUT,
         }
@@ -96,7 +108,9 @@ impl TuyaError {
             ERR_TIMEOUT => TuyaError::Timeout,
             ERR_OFFLINE => TuyaError::Offline,
             ERR_KEY_OR_VER => TuyaError::KeyOrVersionError,
+            ERR_AUTH_REJECTED => TuyaError::AuthRejected,
             ERR_PAYLOAD => TuyaError::InvalidPayload,
+            ERR_FUNCTION => TuyaError::FunctionNotSupported,
             _ => TuyaError::Io(format!("Unknown error code: {code}")),
         }
     }
@@ -119,4 +133,5 @@ define_error_codes! {
     ERR_PARAMS = 912 => "Missing Function Parameters",
     ERR_CLOUD = 913 => "Error Response from Tuya Cloud",
     ERR_KEY_OR_VER = 914 => "Check device key or version",
+    ERR_AUTH_REJECTED = 915 => "Device Reachable but Key Rejected",
 }
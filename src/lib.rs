@@ -14,18 +14,25 @@
 //!
 #[macro_use]
 pub mod macros;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod controller;
 pub mod crypto;
 pub mod device;
+pub mod energy;
 pub mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod protocol;
 pub mod runtime;
 pub mod scanner;
 pub mod sync;
 
+pub use controller::DeviceController;
 pub use device::{Device, DeviceBuilder};
 pub use error::TuyaError;
 pub use protocol::{CommandType, Version};
-pub use runtime::maximize_fd_limit;
+pub use runtime::{maximize_fd_limit, raise_fd_limit};
 pub use scanner::{Scanner, ScannerBuilder};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -5,13 +5,71 @@
 #[macro_export]
 macro_rules! define_command_type {
     ($($name:ident = $val:expr),* $(,)?) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        /// Serialized as its name (e.g. `"Control"`); deserializes from the name or the
+        /// numeric command code. Requires the `serde` feature (on by default).
+        #[cfg_attr(feature = "serde", doc = r##"
+```
+use rustuya::CommandType;
+
+assert_eq!(serde_json::to_string(&CommandType::Control).unwrap(), r#""Control""#);
+assert_eq!(serde_json::from_str::<CommandType>("10").unwrap(), CommandType::DpQuery);
+```"##)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
         #[repr(u32)]
         pub enum CommandType {
             $($name = $val),*
         }
 
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for CommandType {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                #[derive(serde::Deserialize)]
+                #[serde(untagged)]
+                enum Repr {
+                    Code(u32),
+                    Name(String),
+                }
+
+                match Repr::deserialize(deserializer)? {
+                    Repr::Code(code) => CommandType::from_u32(code).ok_or_else(|| {
+                        serde::de::Error::custom(format!("unknown command code: {code}"))
+                    }),
+                    Repr::Name(name) => CommandType::all()
+                        .iter()
+                        .copied()
+                        .find(|cmd| cmd.name() == name)
+                        .ok_or_else(|| {
+                            serde::de::Error::custom(format!("unknown command: {name}"))
+                        }),
+                }
+            }
+        }
+
         impl CommandType {
+            /// Every command type, in declaration order.
+            ///
+            /// ```
+            /// use rustuya::CommandType;
+            ///
+            /// assert!(CommandType::all().contains(&CommandType::DpQuery));
+            /// for cmd in CommandType::all() {
+            ///     assert_eq!(CommandType::from_u32(*cmd as u32), Some(*cmd));
+            /// }
+            /// ```
+            #[must_use]
+            pub const fn all() -> &'static [CommandType] {
+                &[$(CommandType::$name),*]
+            }
+
+            /// Variant name, e.g. `"DpQuery"`.
+            #[must_use]
+            pub const fn name(&self) -> &'static str {
+                match self {
+                    $(CommandType::$name => stringify!($name)),*
+                }
+            }
+
             pub fn from_u32(val: u32) -> Option<Self> {
                 match val {
                     $($val => Some(CommandType::$name)),*
@@ -31,14 +89,72 @@ macro_rules! define_command_type {
 #[macro_export]
 macro_rules! define_version {
     ($($variant:ident = ($str_val:expr, $float_val:expr)),* $(,)?) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+        /// Serialized as its version string (e.g. `"3.3"`); deserializes from that string,
+        /// a number such as `3.3`, or the variant name (`"V3_3"`). Requires the `serde`
+        /// feature (on by default).
+        #[cfg_attr(feature = "serde", doc = r##"
+```
+use rustuya::Version;
+
+assert_eq!(serde_json::to_string(&Version::V3_4).unwrap(), r#""3.4""#);
+assert_eq!(serde_json::from_str::<Version>(r#""3.3""#).unwrap(), Version::V3_3);
+assert_eq!(serde_json::from_str::<Version>("3.5").unwrap(), Version::V3_5);
+assert_eq!(serde_json::from_str::<Version>(r#""V3_1""#).unwrap(), Version::V3_1);
+```"##)]
+        #[derive(Debug, Clone, Copy, PartialEq, Default)]
         pub enum Version {
             #[default]
             Auto,
             $($variant),*
         }
 
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for Version {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for Version {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                #[derive(serde::Deserialize)]
+                #[serde(untagged)]
+                enum Repr {
+                    Num(f64),
+                    Str(String),
+                }
+
+                let found = match Repr::deserialize(deserializer)? {
+                    Repr::Num(n) => Version::all()
+                        .iter()
+                        .copied()
+                        .find(|v| (f64::from(v.val()) - n).abs() < 0.01),
+                    Repr::Str(s) => s.parse().ok().or_else(|| {
+                        Version::all()
+                            .iter()
+                            .copied()
+                            .find(|v| format!("{v:?}") == s)
+                    }),
+                };
+                found.ok_or_else(|| serde::de::Error::custom("invalid version"))
+            }
+        }
+
         impl Version {
+            /// Every concrete protocol version, oldest first. `Auto` is not included.
+            ///
+            /// ```
+            /// use rustuya::Version;
+            ///
+            /// let names: Vec<_> = Version::all().iter().map(Version::as_str).collect();
+            /// assert_eq!(names, ["3.1", "3.2", "3.3", "3.4", "3.5"]);
+            /// ```
+            #[must_use]
+            pub const fn all() -> &'static [Version] {
+                &[$(Version::$variant),*]
+            }
+
             pub fn as_str(&self) -> &'static str {
                 match self {
                     Self::Auto => "Auto",
@@ -106,3 +222,12 @@ macro_rules! define_error_codes {
         }
     };
 }
+
+/// Logs sensitive packet/payload contents at trace level.
+/// Compiled out unless the `trace-payloads` feature is enabled.
+macro_rules! trace_payload {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace-payloads")]
+        log::trace!($($arg)*);
+    };
+}
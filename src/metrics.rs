@@ -0,0 +1,96 @@
+//! Prometheus text exposition of device metrics.
+//!
+//! Renders [`Device::metrics`] for a set of devices so it can be served from any HTTP handler.
+//! Enabled with the `metrics` feature.
+
+use crate::device::Device;
+use std::fmt::Write;
+
+/// Renders metrics for the given devices in the Prometheus text format.
+///
+/// Each series is labeled with the device ID, e.g. `rustuya_device_connected{id="..."} 1`.
+#[must_use]
+pub fn render_prometheus(devices: &[Device]) -> String {
+    let snapshots: Vec<_> = devices
+        .iter()
+        .map(|d| (escape_label(d.id()), d.is_connected(), d.metrics()))
+        .collect();
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, value: &dyn Fn(usize) -> u64| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for (i, (id, _, _)) in snapshots.iter().enumerate() {
+            let _ = writeln!(out, "{name}{{id=\"{id}\"}} {}", value(i));
+        }
+    };
+
+    family(
+        "rustuya_device_connected",
+        "gauge",
+        "Whether the device is currently connected (1) or not (0).",
+        &|i| u64::from(snapshots[i].1),
+    );
+    family(
+        "rustuya_reconnects_total",
+        "counter",
+        "Successful connections after the first one.",
+        &|i| snapshots[i].2.reconnects,
+    );
+    family(
+        "rustuya_connection_failures",
+        "gauge",
+        "Consecutive failed connection attempts.",
+        &|i| u64::from(snapshots[i].2.failure_count),
+    );
+    family(
+        "rustuya_messages_received_total",
+        "counter",
+        "Packets read from the device socket.",
+        &|i| snapshots[i].2.packets_received,
+    );
+    family(
+        "rustuya_messages_sent_total",
+        "counter",
+        "Packets written to the device socket.",
+        &|i| snapshots[i].2.packets_sent,
+    );
+    family(
+        "rustuya_bytes_received_total",
+        "counter",
+        "Bytes read from the device socket.",
+        &|i| snapshots[i].2.bytes_received,
+    );
+    family(
+        "rustuya_bytes_sent_total",
+        "counter",
+        "Bytes written to the device socket.",
+        &|i| snapshots[i].2.bytes_sent,
+    );
+    family(
+        "rustuya_resynced_frames_total",
+        "counter",
+        "Malformed frames dropped by resynchronizing on the next packet prefix.",
+        &|i| snapshots[i].2.resynced_frames,
+    );
+    family(
+        "rustuya_last_handshake_milliseconds",
+        "gauge",
+        "Duration of the last session key negotiation (v3.4/v3.5), 0 if none yet.",
+        &|i| {
+            snapshots[i]
+                .2
+                .last_handshake
+                .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+        },
+    );
+
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
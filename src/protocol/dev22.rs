@@ -68,6 +68,12 @@ impl TuyaProtocol for ProtocolDev22 {
                     payload.insert("ctype".into(), 0.into());
                 }
             }
+            CommandType::SceneExecute | CommandType::LanScene => {
+                payload.remove("gwId");
+                if let Some(scene_id) = payload.remove("dps") {
+                    payload.insert("sceneId".into(), scene_id);
+                }
+            }
             CommandType::Status | CommandType::HeartBeat => {
                 payload.remove("uid");
                 payload.remove("t");
@@ -78,7 +84,8 @@ impl TuyaProtocol for ProtocolDev22 {
         }
 
         let payload_obj = Value::Object(payload);
-        trace!("dev22 generated payload (cmd {cmd_to_send}): {payload_obj}");
+        trace!("dev22 generated payload (cmd {cmd_to_send})");
+        trace_payload!("dev22 payload (cmd {cmd_to_send}): {payload_obj}");
 
         Ok((cmd_to_send, payload_obj))
     }
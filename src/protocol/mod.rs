@@ -7,16 +7,28 @@ use crate::error::{Result, TuyaError};
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use crc::{CRC_32_ISO_HDLC, Crc};
 use hmac::{Hmac, Mac};
+use log::warn;
 use serde::Serialize;
 use serde_json::{Map, Value};
 use sha2::Sha256;
 use std::io::Cursor;
+use tokio::io::AsyncRead;
 
 pub const PREFIX_55AA: u32 = 0x000055AA;
 pub const PREFIX_6699: u32 = 0x00006699;
 pub const SUFFIX_55AA: u32 = 0x0000AA55;
 pub const SUFFIX_6699: u32 = 0x00009966;
 
+/// Largest payload length accepted by [`parse_header`] (64 KiB). Real Tuya frames are far
+/// smaller; anything above this is treated as corrupt rather than allocated.
+pub const MAX_PAYLOAD_LEN: u32 = 64 * 1024;
+/// Smallest 55AA payload length: CRC32 (4) + suffix (4).
+const MIN_PAYLOAD_LEN_55AA: u32 = 8;
+/// Smallest 6699 payload length: IV (12) + GCM tag (16).
+const MIN_PAYLOAD_LEN_6699: u32 = 28;
+/// Default number of junk bytes [`read_prefix`] skips before giving up.
+pub const MAX_PREFIX_SCAN: usize = 1024;
+
 define_command_type! {
     ApConfig = 0x01,
     Active = 0x02,
@@ -61,6 +73,15 @@ pub const NO_PROTOCOL_HEADER_CMDS: &[u32] = &[
     CommandType::LanExtStream as u32,
 ];
 
+/// Commands whose frames never carry a return code: discovery broadcasts and the
+/// client-only halves of the session key negotiation.
+pub const NO_RETCODE_CMDS: &[u32] = &[
+    CommandType::SessKeyNegStart as u32,
+    CommandType::SessKeyNegFinish as u32,
+    CommandType::UdpNew as u32,
+    CommandType::LanPublishAppConfig as u32,
+];
+
 define_version! {
     V3_1 = ("3.1", 3.1),
     V3_2 = ("3.2", 3.2),
@@ -287,6 +308,37 @@ impl TuyaMessage {
             .map(std::string::ToString::to_string)
     }
 
+    /// Extracts the DPS object from a JSON payload.
+    ///
+    /// Handles both the flat `{"dps": {...}}` shape (v3.3 and below) and the nested
+    /// `{"data": {"dps": {...}}}` shape (v3.4+). Returns `None` if the payload has no DPS.
+    ///
+    /// ```
+    /// use rustuya::protocol::TuyaMessage;
+    ///
+    /// let msg = TuyaMessage {
+    ///     payload: br#"{"protocol":4,"data":{"dps":{"1":true,"2":50}}}"#.to_vec(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(msg.dps().unwrap().len(), 2);
+    /// assert_eq!(msg.dp(2), Some(serde_json::json!(50)));
+    /// assert_eq!(msg.dp("3"), None);
+    /// ```
+    #[must_use]
+    pub fn dps(&self) -> Option<Map<String, Value>> {
+        let val: Value = serde_json::from_slice(&self.payload).ok()?;
+        let dps = val
+            .get("dps")
+            .or_else(|| val.get("data").and_then(|d| d.get("dps")))?;
+        dps.as_object().cloned()
+    }
+
+    /// Returns the value of a single DP from the payload, if present.
+    #[must_use]
+    pub fn dp<I: ToString>(&self, index: I) -> Option<Value> {
+        self.dps()?.remove(&index.to_string())
+    }
+
     #[must_use]
     pub fn is_55aa(&self) -> bool {
         self.prefix == PREFIX_55AA
@@ -296,6 +348,34 @@ impl TuyaMessage {
     pub fn is_6699(&self) -> bool {
         self.prefix == PREFIX_6699
     }
+
+    /// Returns the 12-byte GCM nonce of a 6699 (v3.4/v3.5) frame, or `None` for 55AA frames.
+    /// Decoded device messages keep it, including those delivered to `listener()`.
+    ///
+    /// ```
+    /// use rustuya::protocol::{PREFIX_6699, TuyaMessage, pack_message, unpack_message};
+    ///
+    /// let key = b"0123456789abcdef";
+    /// let msg = TuyaMessage {
+    ///     seqno: 1,
+    ///     cmd: 0x10,
+    ///     payload: br#"{"dps":{"1":true}}"#.to_vec(),
+    ///     prefix: PREFIX_6699,
+    ///     iv: Some(b"0123456789ab".to_vec()),
+    ///     ..Default::default()
+    /// };
+    /// let packed = pack_message(&msg, Some(key)).unwrap();
+    /// let unpacked = unpack_message(&packed, Some(key), None, Some(true)).unwrap();
+    /// assert_eq!(unpacked.iv(), Some(&b"0123456789ab"[..]));
+    ///
+    /// let plain = TuyaMessage { seqno: 2, cmd: 0x09, ..Default::default() };
+    /// let packed = pack_message(&plain, None).unwrap();
+    /// assert_eq!(unpack_message(&packed, None, None, Some(true)).unwrap().iv(), None);
+    /// ```
+    #[must_use]
+    pub fn iv(&self) -> Option<&[u8]> {
+        self.iv.as_deref()
+    }
 }
 
 impl Default for TuyaMessage {
@@ -388,7 +468,212 @@ pub fn pack_message(msg: &TuyaMessage, hmac_key: Option<&[u8]>) -> Result<Vec<u8
     Ok(data)
 }
 
+/// Decodes a single raw packet without a live device, e.g. one taken from a traffic capture.
+///
+/// Verifies the frame and decrypts the payload the same way a connected `Device` would.
+/// `key` is the device local key for v3.1–v3.3 and for v3.4/v3.5 handshake frames; data
+/// frames on v3.4/v3.5 are encrypted with the negotiated session key instead (see
+/// `Device::session_key`). `Version::Auto` is treated as v3.3.
+///
+/// ```
+/// use rustuya::protocol::{
+///     CommandType, DeviceType, TuyaMessage, Version, decode_packet, get_protocol, pack_message,
+/// };
+/// use rustuya::crypto::TuyaCipher;
+///
+/// let key = b"0123456789abcdef";
+/// let protocol = get_protocol(Version::V3_3, DeviceType::Default);
+/// let data = serde_json::json!({"1": true});
+/// let (cmd, payload) = protocol
+///     .generate_payload("bf0123456789abcdef", CommandType::Control, Some(data), None, 1700000000)
+///     .unwrap();
+/// let cipher = TuyaCipher::new(key).unwrap();
+/// let packed = pack_message(
+///     &TuyaMessage {
+///         seqno: 1,
+///         cmd,
+///         payload: protocol
+///             .pack_payload(payload.to_string().as_bytes(), cmd, &cipher)
+///             .unwrap(),
+///         ..Default::default()
+///     },
+///     None,
+/// )
+/// .unwrap();
+///
+/// let decoded = decode_packet(&packed, key, Version::V3_3).unwrap();
+/// assert_eq!(decoded.cmd, CommandType::Control as u32);
+/// assert_eq!(decoded.dp(1), Some(serde_json::json!(true)));
+/// ```
+pub fn decode_packet(data: &[u8], key: &[u8], version: Version) -> Result<TuyaMessage> {
+    let protocol = get_protocol(version, DeviceType::Default);
+    let cipher = TuyaCipher::new(key)?;
+    let hmac_key = protocol.get_hmac_key(cipher.key());
+
+    let mut msg = unpack_message(data, hmac_key, None, None)?;
+    if !msg.payload.is_empty() {
+        msg.payload = protocol.decrypt_payload(msg.payload, &cipher)?;
+    }
+    Ok(msg)
+}
+
+/// Re-encrypts and re-frames a decoded message with a new plaintext payload, keeping its
+/// sequence number, command, return code and (for 6699) nonce.
+///
+/// The inverse of [`decode_packet`], for replaying modified captures: the checksum is a CRC32
+/// up to v3.3, an HMAC-SHA256 on v3.4 and the GCM tag on v3.5, as picked by `version`. The
+/// same key rules apply; `Version::Auto` is treated as v3.3.
+///
+/// ```
+/// use rustuya::protocol::{CommandType, TuyaMessage, Version, decode_packet, repack};
+///
+/// let key = b"0123456789abcdef";
+/// for version in [Version::V3_3, Version::V3_4, Version::V3_5] {
+///     let template = TuyaMessage {
+///         seqno: 42,
+///         cmd: CommandType::Status as u32,
+///         ..Default::default()
+///     };
+///     let captured = repack(&template, br#"{"dps":{"1":true}}"#.to_vec(), key, version).unwrap();
+///
+///     let decoded = decode_packet(&captured, key, version).unwrap();
+///     let replay = repack(&decoded, br#"{"dps":{"1":false}}"#.to_vec(), key, version).unwrap();
+///
+///     let replayed = decode_packet(&replay, key, version).unwrap();
+///     assert_eq!(replayed.seqno, 42);
+///     assert_eq!(replayed.dp(1), Some(serde_json::json!(false)));
+/// }
+/// ```
+pub fn repack(
+    original: &TuyaMessage,
+    new_payload: Vec<u8>,
+    key: &[u8],
+    version: Version,
+) -> Result<Vec<u8>> {
+    let protocol = get_protocol(version, DeviceType::Default);
+    let cipher = TuyaCipher::new(key)?;
+    let hmac_key = protocol.get_hmac_key(cipher.key());
+    let prefix = protocol.get_prefix();
+
+    let mut payload = if new_payload.is_empty() {
+        new_payload
+    } else {
+        protocol.pack_payload(&new_payload, original.cmd, &cipher)?
+    };
+    // 55AA frames carry the return code in front of the encrypted payload
+    if prefix == PREFIX_55AA
+        && let Some(rc) = original.retcode
+    {
+        payload.splice(0..0, rc.to_be_bytes());
+    }
+
+    pack_message(
+        &TuyaMessage {
+            seqno: original.seqno,
+            cmd: original.cmd,
+            retcode: original.retcode,
+            payload,
+            prefix,
+            iv: original.iv.clone(),
+        },
+        hmac_key,
+    )
+}
+
+/// Parses a 55AA or 6699 frame header, rejecting lengths outside
+/// `MIN..=`[`MAX_PAYLOAD_LEN`] before anything is allocated for the body.
+///
+/// ```
+/// use rustuya::protocol::parse_header;
+/// use rustuya::TuyaError;
+///
+/// // 55AA header announcing a 4-byte body: too short to even hold the CRC and suffix.
+/// let short = [0, 0, 0x55, 0xaa, 0, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0, 4];
+/// assert!(matches!(parse_header(&short), Err(TuyaError::InvalidHeader)));
+///
+/// // 55AA header announcing a ~4 GiB body.
+/// let huge = [0, 0, 0x55, 0xaa, 0, 0, 0, 1, 0, 0, 0, 7, 0xff, 0xff, 0xff, 0xff];
+/// assert!(matches!(parse_header(&huge), Err(TuyaError::InvalidPayload)));
+///
+/// // 6699 header with a zero-length body (no room for IV and tag).
+/// let empty = [0, 0, 0x66, 0x99, 0, 0, 0, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0, 0];
+/// assert!(matches!(parse_header(&empty), Err(TuyaError::InvalidHeader)));
+///
+/// // Unknown prefix and truncated input.
+/// assert!(parse_header(&[0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8]).is_err());
+/// assert!(parse_header(&[0, 0, 0x55]).is_err());
+/// ```
 pub fn parse_header(data: &[u8]) -> Result<TuyaHeader> {
+    parse_header_with_limit(data, MAX_PAYLOAD_LEN)
+}
+
+/// Reads from `stream` until a 55AA or 6699 prefix is found and returns it.
+///
+/// Bytes before the prefix are discarded one at a time through a sliding 4-byte window, so
+/// a prefix split across junk (e.g. `00 00 55 00 00 55 AA`) is still found without eating
+/// into the real frame. Fails with `DecodeError` once more than `max_skip` bytes have been
+/// skipped. Device connections read through a `BufReader`, so a buffered stream is
+/// recommended to avoid one read call per byte.
+///
+/// ```
+/// use rustuya::protocol::{TuyaMessage, pack_message, parse_header, read_prefix};
+///
+/// let frame = pack_message(&TuyaMessage { seqno: 7, cmd: 0x09, ..Default::default() }, None).unwrap();
+/// let mut data = vec![0xde, 0xad, 0x00, 0x00, 0x55, 0x00, 0x00, 0x55];
+/// data.extend_from_slice(&frame);
+///
+/// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let mut stream = &data[..];
+/// let prefix = rt.block_on(read_prefix(&mut stream, 1024)).unwrap();
+/// assert_eq!(prefix, [0x00, 0x00, 0x55, 0xaa]);
+///
+/// // The rest of the frame is left intact.
+/// let mut header = prefix.to_vec();
+/// header.extend_from_slice(&stream[..12]);
+/// assert_eq!(parse_header(&header).unwrap().seqno, 7);
+///
+/// // Pure garbage is rejected once the skip budget is spent.
+/// let mut junk = &[0xffu8; 64][..];
+/// assert!(rt.block_on(read_prefix(&mut junk, 16)).is_err());
+/// ```
+pub async fn read_prefix<R: AsyncRead + Unpin>(stream: &mut R, max_skip: usize) -> Result<[u8; 4]> {
+    use tokio::io::AsyncReadExt;
+
+    let mut window = [0u8; 4];
+    stream.read_exact(&mut window).await?;
+
+    let mut skipped = 0;
+    loop {
+        let prefix = u32::from_be_bytes(window);
+        if prefix == PREFIX_55AA || prefix == PREFIX_6699 {
+            if skipped > 0 {
+                warn!("Skipped {skipped} bytes before packet prefix");
+            }
+            return Ok(window);
+        }
+        if skipped >= max_skip {
+            return Err(TuyaError::DecodeError(format!(
+                "No packet prefix found in {skipped} bytes"
+            )));
+        }
+        window.rotate_left(1);
+        window[3] = stream.read_u8().await?;
+        skipped += 1;
+    }
+}
+
+/// Like [`parse_header`], with a custom upper bound on the payload length.
+pub fn parse_header_with_limit(data: &[u8], max_payload_len: u32) -> Result<TuyaHeader> {
+    let check_len = |payload_len: u32, min: u32| {
+        if payload_len < min {
+            Err(TuyaError::InvalidHeader)
+        } else if payload_len > max_payload_len {
+            Err(TuyaError::InvalidPayload)
+        } else {
+            Ok(())
+        }
+    };
+
     if data.len() < 16 {
         return Err(TuyaError::DecodeError("Header too short".into()));
     }
@@ -401,7 +686,10 @@ pub fn parse_header(data: &[u8]) -> Result<TuyaHeader> {
             let seqno = cursor.read_u32::<BigEndian>()?;
             let cmd = cursor.read_u32::<BigEndian>()?;
             let payload_len = cursor.read_u32::<BigEndian>()?;
-            let total_length = payload_len + 16;
+            check_len(payload_len, MIN_PAYLOAD_LEN_55AA)?;
+            let total_length = payload_len
+                .checked_add(16)
+                .ok_or(TuyaError::InvalidPayload)?;
             Ok(TuyaHeader {
                 prefix,
                 seqno,
@@ -418,7 +706,10 @@ pub fn parse_header(data: &[u8]) -> Result<TuyaHeader> {
             let seqno = cursor.read_u32::<BigEndian>()?;
             let cmd = cursor.read_u32::<BigEndian>()?;
             let payload_len = cursor.read_u32::<BigEndian>()?;
-            let total_length = payload_len + 18 + 4;
+            check_len(payload_len, MIN_PAYLOAD_LEN_6699)?;
+            let total_length = payload_len
+                .checked_add(18 + 4)
+                .ok_or(TuyaError::InvalidPayload)?;
             Ok(TuyaHeader {
                 prefix,
                 seqno,
@@ -431,11 +722,82 @@ pub fn parse_header(data: &[u8]) -> Result<TuyaHeader> {
     }
 }
 
+/// Decodes a 55AA or 6699 frame.
+///
+/// `no_retcode` says whether the payload starts with a 4-byte return code: `Some(true)` for
+/// none, `Some(false)` to always read one, `None` to detect it. Detection first rules out
+/// [`NO_RETCODE_CMDS`], then looks at the payload bytes.
+///
+/// ```
+/// use rustuya::protocol::{PREFIX_6699, TuyaMessage, pack_message, unpack_message};
+///
+/// let key = b"0123456789abcdef";
+/// let frame = |cmd, retcode, payload: &[u8]| {
+///     let msg = TuyaMessage {
+///         seqno: 1,
+///         cmd,
+///         retcode,
+///         payload: payload.to_vec(),
+///         prefix: PREFIX_6699,
+///         iv: Some(b"0123456789ab".to_vec()),
+///     };
+///     pack_message(&msg, Some(key)).unwrap()
+/// };
+///
+/// // Return code present, followed by JSON.
+/// let msg = unpack_message(&frame(0x10, Some(0), br#"{"dps":{}}"#), Some(key), None, None).unwrap();
+/// assert_eq!((msg.retcode, &msg.payload[..]), (Some(0), &br#"{"dps":{}}"#[..]));
+///
+/// // Return code only: a bare acknowledgement.
+/// let msg = unpack_message(&frame(0x07, Some(1), b""), Some(key), None, None).unwrap();
+/// assert_eq!((msg.retcode, msg.payload.len()), (Some(1), 0));
+///
+/// // No return code: a version-prefixed status push.
+/// let push = b"3.5\0\0\0\0\0\0\0\0\0\0\0\0{\"dps\":{}}";
+/// let msg = unpack_message(&frame(0x08, None, push), Some(key), None, None).unwrap();
+/// assert_eq!((msg.retcode, &msg.payload[..]), (None, &push[..]));
+///
+/// // Ambiguous bytes (5th byte is '{') on a command that never carries a return code.
+/// let odd = br#"abcd{"ip":"10.0.0.2"}"#;
+/// let msg = unpack_message(&frame(0x13, None, odd), Some(key), None, None).unwrap();
+/// assert_eq!((msg.retcode, &msg.payload[..]), (None, &odd[..]));
+/// ```
 pub fn unpack_message(
     data: &[u8],
     hmac_key: Option<&[u8]>,
     header: Option<TuyaHeader>,
     no_retcode: Option<bool>,
+) -> Result<TuyaMessage> {
+    unpack_message_with_verify(data, hmac_key, header, no_retcode, true)
+}
+
+/// Guesses whether a decrypted 6699 payload starts with a return code.
+///
+/// Return codes are small big-endian integers, so a payload that is exactly four bytes or
+/// starts with two zero bytes has one. Otherwise the byte after the would-be code must start
+/// a JSON object or a version header.
+fn detect_retcode_6699(cmd: u32, payload: &[u8]) -> bool {
+    const RETCODE_LEN: usize = 4;
+    if NO_RETCODE_CMDS.contains(&cmd) || payload.len() < RETCODE_LEN || payload[0] == b'{' {
+        return false;
+    }
+    payload.len() == RETCODE_LEN
+        || payload[..2] == [0, 0]
+        || payload[RETCODE_LEN] == b'{'
+        || payload[RETCODE_LEN] == b'3'
+}
+
+/// Like [`unpack_message`], but with control over checksum verification.
+///
+/// With `verify` set to `false`, a CRC32/HMAC mismatch on a 55AA frame is logged as a warning
+/// and the payload is returned anyway. This is meant for off-brand devices that send wrong
+/// checksums. 6699 frames are always authenticated, since the GCM tag is part of decryption.
+pub fn unpack_message_with_verify(
+    data: &[u8],
+    hmac_key: Option<&[u8]>,
+    header: Option<TuyaHeader>,
+    no_retcode: Option<bool>,
+    verify: bool,
 ) -> Result<TuyaMessage> {
     let header = match header {
         Some(h) => h,
@@ -450,7 +812,7 @@ pub fn unpack_message(
         let header_len = 16;
         let end_len = if hmac_key.is_some() { 32 + 4 } else { 4 + 4 };
         let msg_len = header.total_length as usize;
-        let payload_end = msg_len - end_len;
+        let payload_end = msg_len.saturating_sub(end_len);
 
         if payload_end < header_len {
             return Err(TuyaError::DecodeError(format!(
@@ -464,7 +826,8 @@ pub fn unpack_message(
         let should_parse_retcode = match no_retcode {
             Some(no) => !no,
             None => {
-                payload_end - payload_start >= 4
+                !NO_RETCODE_CMDS.contains(&header.cmd)
+                    && payload_end - payload_start >= 4
                     && data[payload_start] != b'{'
                     && (data[payload_start] == 0
                         || (payload_end - payload_start > 4 && data[payload_start] != b'3'))
@@ -488,14 +851,23 @@ pub fn unpack_message(
             mac.update(checksum_data);
             let result = mac.finalize().into_bytes();
             if result.as_slice() != &footer[..32] {
-                return Err(TuyaError::HmacMismatch);
+                if verify {
+                    return Err(TuyaError::HmacMismatch);
+                }
+                warn!("Ignoring HMAC mismatch (cmd: {})", header.cmd);
             }
         } else {
             let crc32 = Crc::<u32>::new(&CRC_32_ISO_HDLC);
             let calc_crc = crc32.checksum(checksum_data);
             let recv_crc = BigEndian::read_u32(&footer[..4]);
             if calc_crc != recv_crc {
-                return Err(TuyaError::CrcMismatch);
+                if verify {
+                    return Err(TuyaError::CrcMismatch);
+                }
+                warn!(
+                    "Ignoring CRC mismatch (cmd: {}, expected: {calc_crc:08x}, got: {recv_crc:08x})",
+                    header.cmd
+                );
             }
         }
 
@@ -515,6 +887,9 @@ pub fn unpack_message(
         let iv_len = 12;
 
         let msg_len = header.total_length as usize;
+        if msg_len < header_len + suffix_len {
+            return Err(TuyaError::InvalidHeader);
+        }
         let payload_with_iv_tag = &data[header_len..msg_len - suffix_len];
 
         if payload_with_iv_tag.len() < iv_len + tag_len {
@@ -540,12 +915,7 @@ pub fn unpack_message(
 
         let should_parse_retcode = match no_retcode {
             Some(no) => !no,
-            None => {
-                payload.len() >= retcode_len
-                    && payload[0] != b'{'
-                    && (payload.len() > retcode_len
-                        && (payload[retcode_len] == b'{' || payload[retcode_len] == b'3'))
-            }
+            None => detect_retcode_6699(header.cmd, &payload),
         };
 
         if should_parse_retcode && payload.len() >= retcode_len {
@@ -6,8 +6,116 @@ use log::trace;
 use md5::{Digest, Md5};
 use serde_json::Value;
 
+/// Tuya protocol v3.1.
+///
+/// Only `Control`/`ControlNew` payloads are encrypted: AES-128-ECB, base64 encoded, and
+/// prefixed with `b"3.1"` plus a 16-char MD5 signature. Queries such as `DpQuery` are sent
+/// as plaintext JSON, as the original firmware expects.
+///
+/// Control round-trip against a known-answer frame:
+///
+/// ```
+/// use rustuya::crypto::TuyaCipher;
+/// use rustuya::protocol::v31::ProtocolV31;
+/// use rustuya::protocol::{CommandType, TuyaMessage, TuyaProtocol, pack_message, unpack_message};
+///
+/// let cipher = TuyaCipher::new(b"0123456789abcdef").unwrap();
+/// let protocol = ProtocolV31;
+/// let data = serde_json::json!({ "1": true });
+/// let (cmd, payload) = protocol
+///     .generate_payload("bf0123456789abcdef", CommandType::Control, Some(data), None, 1700000000)
+///     .unwrap();
+/// let plain = serde_json::to_vec(&payload).unwrap();
+///
+/// let msg = TuyaMessage {
+///     seqno: 1,
+///     cmd,
+///     payload: protocol.pack_payload(&plain, cmd, &cipher).unwrap(),
+///     ..Default::default()
+/// };
+/// let packed = pack_message(&msg, None).unwrap();
+/// assert_eq!(
+///     hex::encode(&packed),
+///     "000055aa00000001000000070000009b332e316664656561376336373165626362336\
+///      37a34596b5834436b50395742366668302b4c7265467967704663715563494c412b7743\
+///      38786934627636686b5962797a6230346a50672f565a756556666a687664364668535331\
+///      534d747a41755877504c697251364e3272716d6265315559786c663964634930574833\
+///      726f344f35706948396f774548705276577664567039e3d876280000aa55"
+/// );
+///
+/// let unpacked = unpack_message(&packed, None, None, Some(true)).unwrap();
+/// assert_eq!(unpacked.cmd, CommandType::Control as u32);
+/// assert_eq!(protocol.decrypt_payload(unpacked.payload, &cipher).unwrap(), plain);
+/// ```
+///
+/// `DpQuery` stays plaintext on the wire:
+///
+/// ```
+/// use rustuya::crypto::TuyaCipher;
+/// use rustuya::protocol::v31::ProtocolV31;
+/// use rustuya::protocol::{CommandType, TuyaMessage, TuyaProtocol, pack_message, unpack_message};
+///
+/// let cipher = TuyaCipher::new(b"0123456789abcdef").unwrap();
+/// let protocol = ProtocolV31;
+/// let (cmd, payload) = protocol
+///     .generate_payload("bf0123456789abcdef", CommandType::DpQuery, None, None, 1700000000)
+///     .unwrap();
+/// let plain = serde_json::to_vec(&payload).unwrap();
+/// let wire = protocol.pack_payload(&plain, cmd, &cipher).unwrap();
+/// assert_eq!(wire, plain);
+///
+/// let msg = TuyaMessage { seqno: 2, cmd, payload: wire, ..Default::default() };
+/// let packed = pack_message(&msg, None).unwrap();
+/// let unpacked = unpack_message(&packed, None, None, Some(true)).unwrap();
+/// assert_eq!(unpacked.cmd, CommandType::DpQuery as u32);
+/// assert_eq!(protocol.decrypt_payload(unpacked.payload, &cipher).unwrap(), plain);
+/// ```
 pub struct ProtocolV31;
 
+impl ProtocolV31 {
+    /// Frames an already encrypted, base64-encoded `Control` payload the way v3.1 firmware
+    /// expects: `b"3.1"` + 16 hex chars of `md5(b"data=" + payload + b"||lpv=3.1||" + key)`
+    /// + payload. Useful for replaying a payload captured from another client.
+    ///
+    /// ```
+    /// use rustuya::crypto::TuyaCipher;
+    /// use rustuya::protocol::v31::ProtocolV31;
+    /// use rustuya::protocol::{CommandType, TuyaProtocol};
+    ///
+    /// let key = b"0123456789abcdef";
+    /// let framed = ProtocolV31::sign(b"zPbu+yJ4hbT1CQOnHLZOwQ==", key);
+    /// assert!(framed.starts_with(b"3.1"));
+    /// assert!(framed[3..19].iter().all(u8::is_ascii_hexdigit));
+    /// assert!(framed.ends_with(b"zPbu+yJ4hbT1CQOnHLZOwQ=="));
+    ///
+    /// // Identical to what `pack_payload` produces for a Control command.
+    /// let cipher = TuyaCipher::new(key).unwrap();
+    /// let packed = ProtocolV31.pack_payload(b"{}", CommandType::Control as u32, &cipher).unwrap();
+    /// assert_eq!(ProtocolV31::sign(&packed[19..], key), packed);
+    /// ```
+    #[must_use]
+    pub fn sign(b64_payload: &[u8], local_key: &[u8]) -> Vec<u8> {
+        Self::sign_as(Version::V3_1, b64_payload, local_key)
+    }
+
+    /// [`sign`](Self::sign) with another version in the header and the `lpv` field, for
+    /// v3.3 firmware that expects the same signed base64 form.
+    pub(crate) fn sign_as(version: Version, b64_payload: &[u8], local_key: &[u8]) -> Vec<u8> {
+        let mut hasher = Md5::new();
+        hasher.update(b"data=");
+        hasher.update(b64_payload);
+        hasher.update(format!("||lpv={version}||").as_bytes());
+        hasher.update(local_key);
+        let md5_hex = hex::encode(hasher.finalize());
+
+        let mut framed = Vec::with_capacity(3 + 16 + b64_payload.len());
+        framed.extend_from_slice(version.as_bytes());
+        framed.extend_from_slice(&md5_hex.as_bytes()[8..24]);
+        framed.extend_from_slice(b64_payload);
+        framed
+    }
+}
+
 impl TuyaProtocol for ProtocolV31 {
     fn version(&self) -> Version {
         Version::V3_1
@@ -67,7 +175,8 @@ impl TuyaProtocol for ProtocolV31 {
         }
 
         let payload_obj = Value::Object(payload);
-        trace!("v3.1 generated payload (cmd {cmd_to_send}): {payload_obj}");
+        trace!("v3.1 generated payload (cmd {cmd_to_send})");
+        trace_payload!("v3.1 payload (cmd {cmd_to_send}): {payload_obj}");
 
         Ok((cmd_to_send, payload_obj))
     }
@@ -77,28 +186,9 @@ impl TuyaProtocol for ProtocolV31 {
             // 1. AES-128-ECB encrypt
             let encrypted = cipher.encrypt(payload, false, None, None, true)?;
 
-            // 2. Base64 encode
+            // 2. Base64 encode, then sign
             let b64_payload = general_purpose::STANDARD.encode(&encrypted);
-            let b64_bytes = b64_payload.as_bytes();
-
-            // 3. Compute MD5 over: b"data=" + base64payload + b"||lpv=3.1||" + local_key
-            let mut hasher = Md5::new();
-            hasher.update(b"data=");
-            hasher.update(b64_bytes);
-            hasher.update(b"||lpv=3.1||");
-            hasher.update(cipher.key());
-
-            let hash = hasher.finalize();
-            let md5_hex = hex::encode(hash);
-            let md5_slice = &md5_hex[8..24];
-
-            // 4. Final payload: b"3.1" + md5slice + base64payload
-            let mut final_payload = Vec::with_capacity(3 + 16 + b64_bytes.len());
-            final_payload.extend_from_slice(b"3.1");
-            final_payload.extend_from_slice(md5_slice.as_bytes());
-            final_payload.extend_from_slice(b64_bytes);
-
-            Ok(final_payload)
+            Ok(Self::sign(b64_payload.as_bytes(), cipher.key()))
         } else {
             Ok(payload.to_vec())
         }
@@ -80,7 +80,8 @@ impl TuyaProtocol for ProtocolV32 {
         }
 
         let payload_obj = Value::Object(payload);
-        trace!("v3.2 generated payload (cmd {cmd_to_send}): {payload_obj}");
+        trace!("v3.2 generated payload (cmd {cmd_to_send})");
+        trace_payload!("v3.2 payload (cmd {cmd_to_send}): {payload_obj}");
 
         Ok((cmd_to_send, payload_obj))
     }
@@ -1,14 +1,51 @@
 use crate::crypto::TuyaCipher;
-use crate::error::Result;
+use crate::error::{Result, TuyaError};
+use crate::protocol::v31::ProtocolV31;
 use crate::protocol::{
     CommandType, NO_PROTOCOL_HEADER_CMDS, TuyaProtocol, Version, create_base_payload,
 };
+use base64::{Engine as _, engine::general_purpose};
 use log::trace;
 use serde_json::Value;
 
 pub struct ProtocolV33;
 
 impl ProtocolV33 {
+    /// Encrypts a `Control` payload in the signed base64 form some v3.3 firmware expects
+    /// instead of the usual raw ECB: AES-128-ECB, base64 encoded, and prefixed with
+    /// `b"3.3"` plus 16 hex chars of `md5(b"data=" + payload + b"||lpv=3.3||" + key)`.
+    /// Used by the device when `DeviceBuilder::control_base64` is enabled.
+    ///
+    /// ```
+    /// use rustuya::crypto::TuyaCipher;
+    /// use rustuya::protocol::v33::ProtocolV33;
+    /// use rustuya::protocol::TuyaProtocol;
+    ///
+    /// let cipher = TuyaCipher::new(b"0123456789abcdef").unwrap();
+    /// let plain = br#"{"devId":"bf0123456789abcdef","dps":{"1":true},"t":"1700000000"}"#;
+    /// let wire = ProtocolV33.pack_control_base64(plain, &cipher).unwrap();
+    /// assert!(wire.starts_with(b"3.3"));
+    /// assert!(wire[3..19].iter().all(u8::is_ascii_hexdigit));
+    /// assert_eq!(ProtocolV33.decrypt_payload(wire, &cipher).unwrap(), plain);
+    /// ```
+    pub fn pack_control_base64(&self, payload: &[u8], cipher: &TuyaCipher) -> Result<Vec<u8>> {
+        let encrypted = cipher.encrypt(payload, false, None, None, true)?;
+        let b64_payload = general_purpose::STANDARD.encode(&encrypted);
+        Ok(ProtocolV31::sign_as(
+            Version::V3_3,
+            b64_payload.as_bytes(),
+            cipher.key(),
+        ))
+    }
+
+    /// Whether `payload` is in the signed base64 form: the 12 zero bytes of the usual
+    /// header are hex digits there.
+    fn is_signed_base64(payload: &[u8]) -> bool {
+        payload.len() > 19
+            && &payload[..3] == Version::V3_3.as_bytes()
+            && payload[3..19].iter().all(u8::is_ascii_hexdigit)
+    }
+
     fn add_protocol_header(&self, payload: &[u8]) -> Vec<u8> {
         let mut header = Version::V3_3.as_bytes().to_vec();
         header.extend_from_slice(&[0u8; 12]);
@@ -66,6 +103,12 @@ impl TuyaProtocol for ProtocolV33 {
                     }
                 }
             }
+            CommandType::SceneExecute | CommandType::LanScene => {
+                payload.remove("gwId");
+                if let Some(scene_id) = payload.remove("dps") {
+                    payload.insert("sceneId".into(), scene_id);
+                }
+            }
             CommandType::Status | CommandType::HeartBeat => {
                 payload.remove("uid");
                 payload.remove("t");
@@ -76,7 +119,8 @@ impl TuyaProtocol for ProtocolV33 {
         }
 
         let payload_obj = Value::Object(payload);
-        trace!("v3.3 generated payload (cmd {cmd_to_send}): {payload_obj}");
+        trace!("v3.3 generated payload (cmd {cmd_to_send})");
+        trace_payload!("v3.3 payload (cmd {cmd_to_send}): {payload_obj}");
 
         Ok((cmd_to_send, payload_obj))
     }
@@ -90,6 +134,12 @@ impl TuyaProtocol for ProtocolV33 {
     }
 
     fn decrypt_payload(&self, mut payload: Vec<u8>, cipher: &TuyaCipher) -> Result<Vec<u8>> {
+        if Self::is_signed_base64(&payload) {
+            let encrypted = general_purpose::STANDARD
+                .decode(&payload[19..])
+                .map_err(|_| TuyaError::DecryptionFailed)?;
+            return cipher.decrypt(&encrypted, false, None, None, None);
+        }
         if payload.len() >= 15 && &payload[..3] == Version::V3_3.as_bytes() {
             payload.drain(..15);
         }
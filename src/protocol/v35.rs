@@ -76,6 +76,12 @@ impl TuyaProtocol for ProtocolV35 {
             CommandType::DpQuery | CommandType::DpQueryNew => {
                 payload.retain(|k, _| k == "cid" || k == "dps");
             }
+            CommandType::SceneExecute | CommandType::LanScene => {
+                payload.remove("gwId");
+                if let Some(scene_id) = payload.remove("dps") {
+                    payload.insert("sceneId".into(), scene_id);
+                }
+            }
             CommandType::Status | CommandType::HeartBeat => {
                 payload.remove("uid");
                 payload.remove("t");
@@ -86,7 +92,8 @@ impl TuyaProtocol for ProtocolV35 {
         }
 
         let payload_obj = Value::Object(payload);
-        trace!("v3.5 generated payload (cmd {cmd_to_send}): {payload_obj}");
+        trace!("v3.5 generated payload (cmd {cmd_to_send})");
+        trace_payload!("v3.5 payload (cmd {cmd_to_send}): {payload_obj}");
 
         Ok((cmd_to_send, payload_obj))
     }
@@ -8,22 +8,54 @@ use tokio::runtime::{Builder, Runtime};
 
 static RUNTIME: OnceLock<Runtime> = OnceLock::new();
 
-/// Maximizes the file descriptor limit (Unix-like system only).
-pub fn maximize_fd_limit() -> Result<()> {
+/// Raises the file descriptor soft limit as far as the hard limit allows (Unix-like systems
+/// only). See [`raise_fd_limit`].
+pub fn maximize_fd_limit() -> Result<usize> {
+    raise_fd_limit(u64::MAX)
+}
+
+/// Raises the file descriptor soft limit toward `target`, capped at the hard limit, and
+/// returns the limit in effect afterwards.
+///
+/// Best-effort: if the system refuses the full amount (e.g. in locked-down containers),
+/// smaller steps toward the target are tried and the best achieved limit is returned with
+/// a warning. Only failing to read the current limit is an error. The limit is never
+/// lowered. On non-Unix systems this does nothing and returns `usize::MAX`.
+pub fn raise_fd_limit(target: u64) -> Result<usize> {
     #[cfg(unix)]
     {
         use crate::error::TuyaError;
-        use log::info;
+        use log::{info, warn};
         let (soft, hard) = rlimit::getrlimit(rlimit::Resource::NOFILE)
             .map_err(|e| TuyaError::Io(format!("Failed to get rlimit: {}", e)))?;
 
-        if soft < hard {
-            rlimit::setrlimit(rlimit::Resource::NOFILE, hard, hard)
-                .map_err(|e| TuyaError::Io(format!("Failed to set rlimit: {}", e)))?;
-            info!("File descriptor limit increased from {} to {}", soft, hard);
+        let wanted = target.min(hard);
+        let mut candidate = wanted;
+        let mut achieved = soft;
+        while candidate > soft {
+            match rlimit::setrlimit(rlimit::Resource::NOFILE, candidate, hard) {
+                Ok(()) => {
+                    achieved = candidate;
+                    break;
+                }
+                // Halve the remaining distance and retry
+                Err(_) => candidate = soft + (candidate - soft) / 2,
+            }
+        }
+
+        if achieved > soft {
+            info!("File descriptor limit increased from {soft} to {achieved}");
         }
+        if achieved < wanted {
+            warn!("File descriptor limit is {achieved}, below the requested {wanted}");
+        }
+        Ok(usize::try_from(achieved).unwrap_or(usize::MAX))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = target;
+        Ok(usize::MAX)
     }
-    Ok(())
 }
 
 pub fn get_runtime() -> &'static Runtime {
@@ -39,6 +71,9 @@ pub fn spawn<F>(future: F) -> tokio::task::JoinHandle<()>
 where
     F: std::future::Future<Output = ()> + Send + 'static,
 {
+    // Child tasks (e.g. a device's reader) stay inside the span of the task that spawned them.
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::in_current_span(future);
     if let Ok(handle) = tokio::runtime::Handle::try_current() {
         handle.spawn(future)
     } else {
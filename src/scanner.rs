@@ -3,37 +3,75 @@
 //! Listens for Tuya broadcast packets on the local network to discover devices.
 
 use crate::crypto::TuyaCipher;
-use crate::error::{Result, TuyaError};
+use crate::error::Result;
 use crate::protocol::{self, CommandType, PREFIX_6699, TuyaMessage, Version};
 use log::{debug, info, trace, warn};
 use parking_lot::RwLock;
 use serde_json::Value;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::net::UdpSocket;
 use tokio::sync::{Notify, mpsc};
 use tokio::time::{Duration, Instant};
 
-use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How a device came to be in the discovery cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DiscoverySource {
+    /// The device announced itself while no active scan was running.
+    #[default]
+    Passive,
+    /// The packet arrived while an active scan (broadcast) was in progress.
+    ActiveResponse,
+    /// The entry was added with `Scanner::insert_known` rather than heard on the network.
+    Manual,
+}
 
 /// Information about a discovered Tuya device.
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DiscoveryResult {
     /// Device ID
     pub id: String,
-    /// Device IP address
+    /// Device IP address, as reported in the discovery payload
     pub ip: String,
+    /// UDP source address the announcement was received from. Can differ from `ip` when
+    /// the device reports a stale address or sits behind NAT.
+    pub observed_addr: Option<SocketAddr>,
     /// Protocol version (e.g., 3.1, 3.3, 3.4, 3.5)
     pub version: Option<Version>,
+    /// TCP port, if the device announces one (most use the default 6668)
+    pub port: Option<u16>,
     /// Product Key
     pub product_key: Option<String>,
-    /// Time when the device was discovered
-    #[serde(skip)]
+    /// Activation state reported by the device (`active`)
+    pub active: Option<u64>,
+    /// Advertised capability flags (`ablilty` in the device JSON)
+    pub ability: Option<u64>,
+    /// Whether the device payloads are encrypted (`encrypt`)
+    pub encrypt: Option<bool>,
+    /// Token flag used during pairing (`token`)
+    pub token: Option<bool>,
+    /// Wi-Fi configuration flag (`wf_cfg`)
+    pub wf_cfg: Option<bool>,
+    /// Raw discovery JSON, including fields not modeled above
+    pub raw: Value,
+    /// Whether the device was heard passively or during an active scan
+    pub source: DiscoverySource,
+    /// Wall-clock time when the device was discovered (seconds since the Unix epoch)
+    pub discovered_at_unix: u64,
+    /// Monotonic time when the device was discovered, used for cache expiry.
+    /// Not serialized; deserialized results are treated as freshly discovered.
+    #[cfg_attr(feature = "serde", serde(skip, default = "Instant::now"))]
     pub discovered_at: Instant,
 }
 
@@ -44,9 +82,35 @@ impl DiscoveryResult {
     pub fn is_same_device(&self, other: &Self) -> bool {
         self.id == other.id
             && self.ip == other.ip
+            && self.observed_addr.map(|a| a.ip()) == other.observed_addr.map(|a| a.ip())
             && self.version == other.version
+            && self.port == other.port
             && self.product_key == other.product_key
     }
+
+    /// Heuristically checks whether the device is waiting to be paired: it announces Wi-Fi
+    /// configuration mode (`wf_cfg: true`) or has not been activated yet (`active` below 2,
+    /// which activated devices report). Missing fields count as paired.
+    #[must_use]
+    pub fn is_unpaired(&self) -> bool {
+        self.wf_cfg == Some(true) || self.active.is_some_and(|active| active < 2)
+    }
+
+    /// Replaces `ip` with the observed source IP when the two differ.
+    #[must_use]
+    pub fn with_observed_ip(mut self) -> Self {
+        if let Some(addr) = self.observed_addr {
+            let observed = addr.ip().to_string();
+            if observed != self.ip {
+                debug!(
+                    "Device {} reports IP {} but was heard from {}",
+                    self.id, self.ip, observed
+                );
+                self.ip = observed;
+            }
+        }
+        self
+    }
 }
 
 /// v3.4 UDP discovery encryption key
@@ -59,10 +123,24 @@ const UDP_KEY_35: &[u8] = UDP_KEY_34;
 const UDP_KEY_33: &[u8] = b"yG9shRKIBrIBUjc3";
 
 const BROADCAST_INTERVAL: Duration = Duration::from_secs(6);
+const BROADCAST_JITTER: Duration = Duration::from_millis(500);
+const MAX_BROADCASTS: u32 = 3;
 const GLOBAL_SCAN_COOLDOWN: Duration = Duration::from_secs(1800); // 30 minutes
 const SCAN_THROTTLE_INTERVAL: Duration = Duration::from_secs(60); // 60 seconds minimum gap between active scans
 const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(18); // Hardcoded 18s timeout
 const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60); // 24 hours
+const CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+const CACHE_POLL_INTERVAL: Duration = Duration::from_millis(250); // Cache re-check while waiting on a scan
+
+/// User override of the discovery broadcast payload, see [`Scanner::set_broadcast_payload`].
+#[derive(Clone)]
+struct BroadcastPayload(Arc<dyn Fn(u16) -> Option<(Value, u32)> + Send + Sync>);
+
+impl std::fmt::Debug for BroadcastPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BroadcastPayload(..)")
+    }
+}
 
 #[derive(Debug)]
 struct ScannerState {
@@ -74,6 +152,11 @@ struct ScannerState {
     cancel_token: tokio_util::sync::CancellationToken,
     sockets: RwLock<HashMap<u16, Arc<UdpSocket>>>,
     receiver_tasks: RwLock<Vec<tokio::task::JoinHandle<()>>>,
+    cache_ttl: RwLock<Duration>,
+    cache_max_entries: RwLock<Option<usize>>,
+    scan_cooldown: RwLock<Duration>,
+    udp_keys: RwLock<Vec<Vec<u8>>>,
+    broadcast_payload: RwLock<Option<BroadcastPayload>>,
 }
 
 impl ScannerState {
@@ -87,6 +170,31 @@ impl ScannerState {
             cancel_token: tokio_util::sync::CancellationToken::new(),
             sockets: RwLock::new(HashMap::new()),
             receiver_tasks: RwLock::new(Vec::new()),
+            cache_ttl: RwLock::new(CACHE_TTL),
+            cache_max_entries: RwLock::new(None),
+            scan_cooldown: RwLock::new(GLOBAL_SCAN_COOLDOWN),
+            udp_keys: RwLock::new(Vec::new()),
+            broadcast_payload: RwLock::new(None),
+        }
+    }
+
+    /// Drops entries older than the TTL, then the least recently seen ones above the size cap.
+    fn prune_cache(&self, cache: &mut HashMap<String, DiscoveryResult>) {
+        let ttl = *self.cache_ttl.read();
+        cache.retain(|_, v| v.discovered_at.elapsed() < ttl);
+
+        if let Some(max) = *self.cache_max_entries.read()
+            && cache.len() > max
+        {
+            let excess = cache.len() - max;
+            let mut by_age: Vec<_> = cache
+                .iter()
+                .map(|(id, v)| (v.discovered_at, id.clone()))
+                .collect();
+            by_age.sort_unstable();
+            for (_, id) in by_age.into_iter().take(excess) {
+                cache.remove(&id);
+            }
         }
     }
 }
@@ -110,6 +218,21 @@ pub struct Scanner {
     pub bind_addr: String,
     /// UDP ports to scan (default: 6666, 6667, 7000)
     pub ports: Vec<u16>,
+    /// Interval between discovery broadcasts during an active scan (default: 6s)
+    pub broadcast_interval: Duration,
+    /// Maximum random delay added before each broadcast to avoid synchronized bursts (default: 500ms)
+    pub broadcast_jitter: Duration,
+    /// Maximum number of broadcasts per scan; `None` rebroadcasts until the timeout (default: 3)
+    pub max_broadcasts: Option<u32>,
+    /// Broadcast on every local IPv4 interface instead of only the default route (default: false)
+    pub all_interfaces: bool,
+    /// Broadcast only on the named network interface, e.g. `"eth1"` (default: none)
+    pub interface: Option<String>,
+    /// Report the UDP source address as `ip` when it differs from the payload (default: false)
+    pub use_source_ip: bool,
+    /// Keep UDP ports bound in the background for passive discovery (default: true).
+    /// When disabled, sockets are bound only for the duration of an active scan.
+    pub passive_listener: bool,
 }
 
 impl Default for Scanner {
@@ -141,6 +264,11 @@ impl Scanner {
         get()
     }
 
+    /// Creates a new Scanner builder.
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::new()
+    }
+
     /// Creates a new Scanner with default settings.
     #[must_use]
     pub(crate) fn new() -> Self {
@@ -149,13 +277,60 @@ impl Scanner {
             timeout: DEFAULT_SCAN_TIMEOUT,
             bind_addr: "0.0.0.0".to_string(),
             ports: vec![6666, 6667, 7000],
+            broadcast_interval: BROADCAST_INTERVAL,
+            broadcast_jitter: BROADCAST_JITTER,
+            max_broadcasts: Some(MAX_BROADCASTS),
+            all_interfaces: false,
+            interface: None,
+            use_source_ip: false,
+            passive_listener: true,
         };
         scanner.ensure_passive_listener();
         scanner
     }
 
+    /// Creates a scanner with default settings and its own cache, scan state, and passive
+    /// listener, independent of the global instance from [`get`](Self::get).
+    ///
+    /// Discoveries, cooldowns, and `stop_passive_listener()` on one isolated scanner are
+    /// not seen by any other, so each network segment or test can own one. Clones share
+    /// the instance they were cloned from.
+    ///
+    /// ```
+    /// use rustuya::Version;
+    /// use rustuya::scanner::Scanner;
+    /// use std::time::Duration;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let a = Scanner::new_isolated();
+    /// let b = Scanner::new_isolated();
+    /// a.insert_known("bf0123456789abcdef", "192.168.1.40", Version::V3_4);
+    ///
+    /// let within = Duration::from_secs(60);
+    /// assert!(a.is_recently_discovered("bf0123456789abcdef", within));
+    /// assert!(!b.is_recently_discovered("bf0123456789abcdef", within));
+    /// assert!(!Scanner::get().is_recently_discovered("bf0123456789abcdef", within));
+    /// # a.stop_passive_listener();
+    /// # b.stop_passive_listener();
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn new_isolated() -> Self {
+        Self::new()
+    }
+
+    /// Creates a scanner with its own cache that binds UDP ports only while an active
+    /// scan is running, without starting the background passive listener.
+    #[must_use]
+    pub fn without_passive_listener() -> Self {
+        ScannerBuilder::new().passive_listener(false).build()
+    }
+
     /// Ensures background passive listener is running.
     fn ensure_passive_listener(&self) {
+        if !self.passive_listener {
+            return;
+        }
         let state = &self.inner;
         let mut ports_to_add = Vec::new();
         {
@@ -211,46 +386,23 @@ impl Scanner {
 
             crate::runtime::spawn(async move {
                 debug!("Starting background passive listener task...");
+                let mut prune_timer = tokio::time::interval(CACHE_PRUNE_INTERVAL);
 
                 loop {
                     tokio::select! {
                         () = cancel_token.cancelled() => break,
-                        Some((data, _addr)) = rx.recv() => {
+                        _ = prune_timer.tick() => {
+                            // Expire entries even when no packets arrive to trigger pruning.
+                            let Some(state) = state_weak.upgrade() else { break };
+                            state.prune_cache(&mut state.cache.write());
+                        }
+                        Some((data, addr)) = rx.recv() => {
                             let state = match state_weak.upgrade() {
                                 Some(s) => s,
                                 None => break,
                             };
 
-                            // We need to parse the packet. Since parse_packet is a method of Scanner,
-                            // but we want to avoid holding a Scanner (which holds an Arc),
-                            // we use a temporary Scanner instance for parsing.
-                            let temp_scanner = Scanner {
-                                inner: state.clone(),
-                                timeout: Duration::from_secs(0),
-                                bind_addr: String::new(),
-                                ports: Vec::new(),
-                            };
-
-                            if let Some(res) = temp_scanner.parse_packet(&data) {
-                                let mut guard = state.cache.write();
-
-                                // Keep memory clean by removing expired entries on every update.
-                                guard.retain(|_, v| v.discovered_at.elapsed() < CACHE_TTL);
-
-                                let should_log = match guard.get(&res.id) {
-                                    Some(existing) => !res.is_same_device(existing),
-                                    None => true,
-                                };
-
-                                if should_log {
-                                    let mode = if state.active_scanning.load(Ordering::SeqCst) { "A" } else { "P" };
-                                    let version = res.version.map_or_else(|| "unknown".to_string(), |v| v.to_string());
-                                    info!("Discovered device {}(v{}) at {} - {}", res.id, version, res.ip, mode);
-                                }
-
-                                guard.insert(res.id.clone(), res.clone());
-                                state.notify.notify_waiters();
-                            }
+                            Self::ingest_packet(&state, &data, addr);
                         }
                     }
                 }
@@ -259,6 +411,58 @@ impl Scanner {
         }
     }
 
+    /// Parses a discovery packet and records the result in the shared cache.
+    fn ingest_packet(state: &Arc<ScannerState>, data: &[u8], addr: SocketAddr) {
+        // We need to parse the packet. Since parse_packet is a method of Scanner,
+        // but we want to avoid holding a Scanner (which holds an Arc),
+        // we use a temporary Scanner instance for parsing.
+        let temp_scanner = Scanner {
+            inner: state.clone(),
+            timeout: Duration::from_secs(0),
+            bind_addr: String::new(),
+            ports: Vec::new(),
+            broadcast_interval: BROADCAST_INTERVAL,
+            broadcast_jitter: Duration::ZERO,
+            max_broadcasts: None,
+            all_interfaces: false,
+            interface: None,
+            use_source_ip: false,
+            passive_listener: false,
+        };
+
+        if let Some(mut res) = temp_scanner.parse_packet(data, addr) {
+            if state.active_scanning.load(Ordering::SeqCst) {
+                res.source = DiscoverySource::ActiveResponse;
+            }
+            let mut guard = state.cache.write();
+
+            let should_log = match guard.get(&res.id) {
+                Some(existing) => !res.is_same_device(existing),
+                None => true,
+            };
+
+            if should_log {
+                let mode = match res.source {
+                    DiscoverySource::ActiveResponse => "A",
+                    DiscoverySource::Passive => "P",
+                    DiscoverySource::Manual => "M",
+                };
+                let version = res
+                    .version
+                    .map_or_else(|| "unknown".to_string(), |v| v.to_string());
+                info!(
+                    "Discovered device {}(v{}) at {} - {}",
+                    res.id, version, res.ip, mode
+                );
+            }
+
+            guard.insert(res.id.clone(), res.clone());
+            // Keep memory bounded by pruning on every update.
+            state.prune_cache(&mut guard);
+            state.notify.notify_waiters();
+        }
+    }
+
     fn spawn_receiver_tasks(
         sockets: Vec<Arc<UdpSocket>>,
         cancel_token: tokio_util::sync::CancellationToken,
@@ -277,6 +481,9 @@ impl Scanner {
                         () = ct.cancelled() => break,
                         res = socket.recv_from(&mut buf) => {
                             match res {
+                                // An empty datagram is a real (if useless) packet, and each
+                                // one is consumed by the read, so skipping it cannot spin.
+                                Ok((0, addr)) => trace!("Ignoring empty datagram from {addr}"),
                                 Ok((len, addr)) => {
                                     if tx.send((buf[..len].to_vec(), addr)).await.is_err() {
                                         break;
@@ -314,9 +521,49 @@ impl Scanner {
         Ok(UdpSocket::from_std(std_socket)?)
     }
 
-    /// Stops background passive listener.
+    /// Stops the background passive listener and aborts its socket receiver tasks, so the
+    /// discovery ports are released.
+    ///
+    /// ```
+    /// use rustuya::scanner::Scanner;
+    /// use std::net::UdpSocket;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// // Let the OS pick a free port for the scanner.
+    /// let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    /// let target = ("127.0.0.1", port);
+    /// let scanner = Scanner::builder().bind_addr("127.0.0.1").ports(vec![port]).build();
+    /// let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    ///
+    /// // A flood of empty datagrams is skipped without disturbing the listener.
+    /// for _ in 0..1000 {
+    ///     sender.send_to(&[], target).unwrap();
+    /// }
+    /// // Devices announce repeatedly; keep sending in case the flood filled the buffer.
+    /// let announce = r#"{"gwId":"dev_a","ip":"127.0.0.1","version":"3.3"}"#;
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while !scanner.is_recently_discovered("dev_a", Duration::from_secs(60)) {
+    ///     assert!(Instant::now() < deadline, "listener stopped after empty datagrams");
+    ///     sender.send_to(announce.as_bytes(), target).unwrap();
+    ///     tokio::time::sleep(Duration::from_millis(10)).await;
+    /// }
+    ///
+    /// // The port is held until the listener is stopped, then released.
+    /// assert!(UdpSocket::bind(target).is_err());
+    /// scanner.stop_passive_listener();
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while UdpSocket::bind(target).is_err() {
+    ///     assert!(Instant::now() < deadline, "port still bound after stop");
+    ///     tokio::time::sleep(Duration::from_millis(10)).await;
+    /// }
+    /// # });
+    /// ```
     pub fn stop_passive_listener(&self) {
         self.inner.cancel_token.cancel();
+        for task in self.inner.receiver_tasks.write().drain(..) {
+            task.abort();
+        }
         self.inner.listener_started.store(false, Ordering::SeqCst);
         self.inner.sockets.write().clear();
     }
@@ -360,6 +607,83 @@ impl Scanner {
         s
     }
 
+    /// Sets how long a discovered device stays in the cache without being seen again
+    /// (default: 24 hours). Applies to every scanner sharing this cache.
+    pub fn set_cache_ttl(&self, ttl: Duration) {
+        *self.inner.cache_ttl.write() = ttl;
+        self.inner.prune_cache(&mut self.inner.cache.write());
+    }
+
+    /// Caps the number of cached devices, evicting the least recently seen ones first
+    /// (default: `None`, unbounded). Applies to every scanner sharing this cache.
+    pub fn set_cache_max_entries(&self, max: Option<usize>) {
+        *self.inner.cache_max_entries.write() = max;
+        self.inner.prune_cache(&mut self.inner.cache.write());
+    }
+
+    /// Sets how long cached results are trusted and new scans are held back (default:
+    /// 30 minutes). Within this window a device lookup is answered from the cache, and
+    /// `scan_stream()` does not start another broadcast scan. Lookups with a forced scan
+    /// (as done by a `Device` after its connection fails, via `force_discovery`) skip
+    /// the cache and cooldown and scan right away unless a scan is already running.
+    /// Lower it where DHCP moves devices often. Applies to every scanner sharing this cache.
+    pub fn set_scan_cooldown(&self, cooldown: Duration) {
+        *self.inner.scan_cooldown.write() = cooldown;
+    }
+
+    /// Returns a scanner that broadcasts on every local IPv4 interface.
+    /// Use this when devices live on several subnets (e.g., separate IoT VLANs).
+    #[must_use]
+    pub fn with_all_interfaces(&self) -> Self {
+        let mut s = self.clone();
+        s.all_interfaces = true;
+        s
+    }
+
+    /// Returns a scanner that reports each device's UDP source address as its `ip`,
+    /// for devices that announce a stale or NAT-internal address.
+    #[must_use]
+    pub fn with_source_ip(&self) -> Self {
+        let mut s = self.clone();
+        s.use_source_ip = true;
+        s
+    }
+
+    /// Adds UDP keys to try when decoding announcements, for rebranded firmware using a
+    /// vendor-specific key. They are tried after the three built-in keys. Since the passive
+    /// listener decodes for the whole cache, the keys apply to every scanner sharing it.
+    pub fn add_udp_keys(&self, keys: Vec<Vec<u8>>) {
+        let mut udp_keys = self.inner.udp_keys.write();
+        for key in keys {
+            if !udp_keys.contains(&key) {
+                udp_keys.push(key);
+            }
+        }
+    }
+
+    /// Overrides the payload of the discovery broadcasts, for firmware that only answers a
+    /// broadcast with a specific `from` or app signature.
+    ///
+    /// The closure gets the UDP port and returns the JSON payload with the frame prefix
+    /// (`PREFIX_55AA` or `PREFIX_6699`), or `None` to keep the default for that port.
+    /// 6699 frames are encrypted with the v3.5 UDP key. Applies to every scanner sharing
+    /// this one's cache.
+    pub fn set_broadcast_payload<F>(&self, payload: F)
+    where
+        F: Fn(u16) -> Option<(Value, u32)> + Send + Sync + 'static,
+    {
+        *self.inner.broadcast_payload.write() = Some(BroadcastPayload(Arc::new(payload)));
+    }
+
+    /// Applies per-scanner presentation settings to a cached result.
+    fn present(&self, res: DiscoveryResult) -> DiscoveryResult {
+        if self.use_source_ip {
+            res.with_observed_ip()
+        } else {
+            res
+        }
+    }
+
     /// Returns a future that resolves when any device is discovered.
     pub fn notified(&self) -> tokio::sync::futures::Notified<'_> {
         self.inner.notify.notified()
@@ -381,11 +705,58 @@ impl Scanner {
         socket.local_addr().ok().map(|addr| addr.ip().to_string())
     }
 
-    async fn send_discovery_broadcast(&self, socket: &UdpSocket, port: u16) -> Result<()> {
+    /// Returns `(broadcast address, local IP)` pairs to send discovery broadcasts to.
+    fn broadcast_targets(&self) -> Vec<(Ipv4Addr, String)> {
+        if self.all_interfaces || self.interface.is_some() {
+            match if_addrs::get_if_addrs() {
+                Ok(ifaces) => {
+                    let targets: Vec<_> = ifaces
+                        .into_iter()
+                        .filter(|iface| !iface.is_loopback())
+                        .filter(|iface| self.interface.as_ref().is_none_or(|n| *n == iface.name))
+                        .filter_map(|iface| match iface.addr {
+                            if_addrs::IfAddr::V4(v4) => {
+                                v4.broadcast.map(|b| (b, v4.ip.to_string()))
+                            }
+                            if_addrs::IfAddr::V6(_) => None,
+                        })
+                        .collect();
+                    if !targets.is_empty() {
+                        return targets;
+                    }
+                    match &self.interface {
+                        Some(name) => warn!(
+                            "Interface {name} has no IPv4 broadcast address, using default route"
+                        ),
+                        None => warn!("No IPv4 broadcast interfaces found, using default route"),
+                    }
+                }
+                Err(e) => warn!("Failed to enumerate network interfaces: {e}"),
+            }
+        }
+
         let local_ip = self.get_local_ip().unwrap_or_else(|| "0.0.0.0".to_string());
+        vec![(Ipv4Addr::BROADCAST, local_ip)]
+    }
+
+    async fn send_discovery_broadcast(
+        &self,
+        socket: &UdpSocket,
+        port: u16,
+        broadcast_ip: Ipv4Addr,
+        local_ip: &str,
+    ) -> Result<()> {
         debug!("Sending discovery broadcast on port {port} (local IP: {local_ip})");
 
-        let (payload, prefix) = if port == 7000 {
+        let custom = self
+            .inner
+            .broadcast_payload
+            .read()
+            .clone()
+            .and_then(|hook| (hook.0)(port));
+        let (payload, prefix) = if let Some(custom) = custom {
+            custom
+        } else if port == 7000 {
             (
                 serde_json::json!({
                     "from": "app",
@@ -405,7 +776,7 @@ impl Scanner {
 
         let msg = TuyaMessage {
             seqno: 0,
-            cmd: if port == 7000 {
+            cmd: if prefix == PREFIX_6699 {
                 CommandType::ReqDevInfo as u32
             } else {
                 CommandType::UdpNew as u32
@@ -416,11 +787,9 @@ impl Scanner {
             iv: None,
         };
 
-        let packed =
-            protocol::pack_message(&msg, if port == 7000 { Some(UDP_KEY_35) } else { None })?;
-        let broadcast_addr: SocketAddr = format!("255.255.255.255:{port}")
-            .parse()
-            .map_err(|_| TuyaError::Offline)?;
+        let key = (prefix == PREFIX_6699).then_some(UDP_KEY_35);
+        let packed = protocol::pack_message(&msg, key)?;
+        let broadcast_addr = SocketAddr::from((broadcast_ip, port));
 
         match socket.send_to(&packed, broadcast_addr).await {
             Ok(len) => debug!("Sent discovery broadcast to {broadcast_addr}: {len} bytes"),
@@ -447,11 +816,12 @@ impl Scanner {
         let timeout_dur = self.timeout;
         let start_time = Instant::now();
         let scanner = self.clone();
+        let presenter = self.clone();
 
         // 1. Start a new scan if none is in progress and cooldown has passed
         let should_start = !state.active_scanning.load(Ordering::SeqCst) && {
             let last_scan = state.last_scan_time.read();
-            last_scan.is_none_or(|t| t.elapsed() >= GLOBAL_SCAN_COOLDOWN)
+            last_scan.is_none_or(|t| t.elapsed() >= *state.scan_cooldown.read())
         };
 
         if should_start {
@@ -476,7 +846,7 @@ impl Scanner {
 
             for item in initial_items {
                 yielded_ids.insert(item.id.clone());
-                yield item;
+                yield presenter.present(item);
             }
 
             // 3. Yield new items as they are discovered
@@ -502,7 +872,7 @@ impl Scanner {
 
                         for item in new_items {
                             yielded_ids.insert(item.id.clone());
-                            yield item;
+                            yield presenter.present(item);
                         }
 
                         // If scanning finished, we can stop after checking the cache one last time
@@ -516,7 +886,7 @@ impl Scanner {
                                     .collect()
                             };
                             for item in final_items {
-                                yield item;
+                                yield presenter.present(item);
                             }
                             break;
                         }
@@ -549,6 +919,26 @@ impl Scanner {
         Ok(results)
     }
 
+    /// Like `scan_instance`, but with a timeout for this call only.
+    /// The scanner's own `timeout` field is left untouched.
+    pub async fn scan_with_timeout(&self, timeout: Duration) -> Result<Vec<DiscoveryResult>> {
+        self.with_timeout(timeout).scan_instance().await
+    }
+
+    /// Scans the local network and returns only devices that look unpaired, see
+    /// [`DiscoveryResult::is_unpaired`]. The `token` and `encrypt` flags are kept on each
+    /// result for pairing workflows.
+    pub async fn discover_unpaired() -> Result<Vec<DiscoveryResult>> {
+        Self::get().discover_unpaired_instance().await
+    }
+
+    /// Instance version of `discover_unpaired`.
+    pub async fn discover_unpaired_instance(&self) -> Result<Vec<DiscoveryResult>> {
+        let mut results = self.scan_instance().await?;
+        results.retain(DiscoveryResult::is_unpaired);
+        Ok(results)
+    }
+
     /// Discovers a specific device by ID.
     pub async fn discover_device(device_id: &str) -> Result<Option<DiscoveryResult>> {
         Self::get().discover_device_instance(device_id).await
@@ -562,6 +952,18 @@ impl Scanner {
         self.discover_device_internal(device_id, false).await
     }
 
+    /// Like `discover_device_instance`, but with a timeout for this call only.
+    /// The global scan cooldown is still respected.
+    pub async fn discover_device_with_timeout(
+        &self,
+        device_id: &str,
+        timeout: Duration,
+    ) -> Result<Option<DiscoveryResult>> {
+        self.with_timeout(timeout)
+            .discover_device_internal(device_id, false)
+            .await
+    }
+
     pub async fn discover_device_internal(
         &self,
         device_id: &str,
@@ -585,23 +987,27 @@ impl Scanner {
         force_scan: bool,
     ) -> Option<DiscoveryResult> {
         let state = &self.inner;
+        let cooldown = *state.scan_cooldown.read();
         let guard = state.cache.read();
 
         if let Some(res) = guard.get(device_id).cloned()
             && !force_scan
-            && res.discovered_at.elapsed() < GLOBAL_SCAN_COOLDOWN
+            && res.discovered_at.elapsed() < cooldown
         {
             debug!("Found device {device_id} in discovery cache");
-            return Some(res);
+            return Some(self.present(res));
         }
 
         if !force_scan
             && let Some(last) = *state.last_scan_time.read()
-            && last.elapsed() < GLOBAL_SCAN_COOLDOWN
+            && last.elapsed() < cooldown
             && let Some(res) = guard.get(device_id).cloned()
         {
-            debug!("Global scan cooldown active (30m). Returning cached result for {device_id}.");
-            return Some(res);
+            debug!(
+                "Global scan cooldown active ({}s). Returning cached result for {device_id}.",
+                cooldown.as_secs()
+            );
+            return Some(self.present(res));
         }
         None
     }
@@ -634,18 +1040,29 @@ impl Scanner {
         let start_wait = Instant::now();
 
         loop {
+            // Register before checking so an insert between the check and the wait is not
+            // missed, and poll as well so a waiter never depends on another caller's scan.
+            let notified = state.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
             if let Some(res) = state.cache.read().get(device_id).cloned() {
-                return Some(res);
+                return Some(self.present(res));
             }
 
             let elapsed = start_wait.elapsed();
             if elapsed >= self.timeout || !state.active_scanning.load(Ordering::SeqCst) {
                 // One last check before giving up
-                return state.cache.read().get(device_id).cloned();
+                return state
+                    .cache
+                    .read()
+                    .get(device_id)
+                    .cloned()
+                    .map(|res| self.present(res));
             }
 
             let remaining = self.timeout.saturating_sub(elapsed);
-            let _ = tokio::time::timeout(remaining, state.notify.notified()).await;
+            let _ = tokio::time::timeout(remaining.min(CACHE_POLL_INTERVAL), notified).await;
         }
     }
 
@@ -673,38 +1090,89 @@ impl Scanner {
             }
         }
 
+        // Without a passive listener, bind sockets just for this scan. They are released
+        // when `_scan_guard` cancels the receivers at the end of the scan.
+        let scan_token = state.cancel_token.child_token();
+        let _scan_guard = scan_token.clone().drop_guard();
+        if target_sockets.is_empty() && !self.passive_listener {
+            target_sockets = self
+                .ports
+                .iter()
+                .filter_map(|&port| {
+                    Self::create_udp_socket(&self.bind_addr, port)
+                        .ok()
+                        .map(|socket| (Arc::new(socket), port))
+                })
+                .collect();
+            let sockets = target_sockets.iter().map(|(s, _)| s.clone()).collect();
+            let (mut rx, _) = Self::spawn_receiver_tasks(sockets, scan_token.clone());
+            let state = state.clone();
+            crate::runtime::spawn(async move {
+                loop {
+                    tokio::select! {
+                        () = scan_token.cancelled() => break,
+                        Some((data, addr)) = rx.recv() => Self::ingest_packet(&state, &data, addr),
+                    }
+                }
+            });
+        }
+
         if target_sockets.is_empty() {
             return Err(std::io::Error::other("No available ports for scanning").into());
         }
 
         let start = Instant::now();
-        let mut broadcast_interval = tokio::time::interval(BROADCAST_INTERVAL);
         let mut broadcast_count = 0;
+        let remaining = || self.timeout.saturating_sub(start.elapsed());
 
-        while start.elapsed() < self.timeout {
-            let remaining = self.timeout.saturating_sub(start.elapsed());
-            if remaining.is_zero() {
+        while !remaining().is_zero() {
+            if self
+                .max_broadcasts
+                .is_some_and(|max| broadcast_count >= max)
+            {
+                tokio::time::sleep(remaining()).await;
                 break;
             }
 
-            tokio::select! {
-                () = tokio::time::sleep(remaining) => break,
-                _ = broadcast_interval.tick() => {
-                    if broadcast_count < 3 {
-                        broadcast_count += 1;
-                        debug!("Sent broadcast {broadcast_count}/3");
-                        for (socket, port) in &target_sockets {
-                            let _ = self.send_discovery_broadcast(socket, *port).await;
-                        }
-                    }
+            tokio::time::sleep(self.random_jitter().min(remaining())).await;
+            if remaining().is_zero() {
+                break;
+            }
+
+            broadcast_count += 1;
+            for (broadcast_ip, local_ip) in self.broadcast_targets() {
+                for (socket, port) in &target_sockets {
+                    let _ = self
+                        .send_discovery_broadcast(socket, *port, broadcast_ip, &local_ip)
+                        .await;
                 }
             }
+            match self.max_broadcasts {
+                Some(max) => debug!("Sent broadcast {broadcast_count}/{max}"),
+                None => debug!("Sent broadcast {broadcast_count}"),
+            }
+
+            tokio::time::sleep(self.broadcast_interval.min(remaining())).await;
         }
 
         Ok(())
     }
 
-    fn parse_packet(&self, data: &[u8]) -> Option<DiscoveryResult> {
+    fn random_jitter(&self) -> Duration {
+        let max_ms = self.broadcast_jitter.as_millis() as u64;
+        if max_ms == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::RngCore::next_u64(&mut rand::rng()) % (max_ms + 1))
+    }
+
+    fn parse_packet(&self, data: &[u8], addr: SocketAddr) -> Option<DiscoveryResult> {
+        let mut res = self.parse_payload(data)?;
+        res.observed_addr = Some(addr);
+        Some(res)
+    }
+
+    fn parse_payload(&self, data: &[u8]) -> Option<DiscoveryResult> {
         trace!("Parsing UDP packet of {} bytes...", data.len());
 
         // 1. Try raw JSON (v3.1, port 6666)
@@ -714,7 +1182,8 @@ impl Scanner {
         }
 
         // 2. Try Tuya message format (55AA or 6699)
-        let tries: &[(Option<&[u8]>, Option<bool>)] = &[
+        let extra_keys = self.inner.udp_keys.read().clone();
+        let mut tries: Vec<(Option<&[u8]>, Option<bool>)> = vec![
             (Some(UDP_KEY_35), Some(true)),
             (Some(UDP_KEY_35), Some(false)),
             (Some(UDP_KEY_35), None),
@@ -724,13 +1193,16 @@ impl Scanner {
             (Some(UDP_KEY_33), Some(true)),
             (Some(UDP_KEY_33), Some(false)),
             (Some(UDP_KEY_33), None),
-            (None, Some(true)),
-            (None, Some(false)),
-            (None, None),
         ];
+        for key in &extra_keys {
+            for no_retcode in [Some(true), Some(false), None] {
+                tries.push((Some(key), no_retcode));
+            }
+        }
+        tries.extend([(None, Some(true)), (None, Some(false)), (None, None)]);
 
-        for (key, no_retcode) in tries {
-            match protocol::unpack_message(data, *key, None, *no_retcode) {
+        for &(key, no_retcode) in &tries {
+            match protocol::unpack_message(data, key, None, no_retcode) {
                 Ok(msg) => {
                     if msg.payload.is_empty() {
                         continue;
@@ -744,9 +1216,11 @@ impl Scanner {
 
                     // 2b. Payload is ECB encrypted (v3.3/v3.4)
                     let keys_to_try = if let Some(k) = key {
-                        vec![*k]
+                        vec![k]
                     } else {
-                        vec![UDP_KEY_33, UDP_KEY_34, UDP_KEY_35]
+                        let mut keys = vec![UDP_KEY_33, UDP_KEY_34, UDP_KEY_35];
+                        keys.extend(extra_keys.iter().map(Vec::as_slice));
+                        keys
                     };
 
                     for k in keys_to_try {
@@ -782,7 +1256,10 @@ impl Scanner {
         }
 
         // 3. Try to decrypt the entire packet as AES-ECB (v3.3 discovery fallback)
-        for key in &[UDP_KEY_33, UDP_KEY_34] {
+        let fallback_keys = [UDP_KEY_33, UDP_KEY_34]
+            .into_iter()
+            .chain(extra_keys.iter().map(Vec::as_slice));
+        for key in fallback_keys {
             if let Ok(cipher) = TuyaCipher::new(key)
                 && let Ok(decrypted) = cipher.decrypt(data, false, None, None, None)
                 && let Ok(val) = serde_json::from_slice::<Value>(&decrypted)
@@ -804,6 +1281,66 @@ impl Scanner {
         None
     }
 
+    /// Seeds the discovery cache with a device whose address is already known (e.g. from
+    /// a DHCP lease), so lookups for it are answered without a UDP scan. `Version::Auto`
+    /// leaves the version unknown. The entry is treated like a fresh discovery: it is
+    /// trusted for the scan cooldown, and replaced as soon as the device announces itself.
+    ///
+    /// ```
+    /// use rustuya::Version;
+    /// use rustuya::scanner::{DiscoverySource, Scanner};
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let scanner = Scanner::without_passive_listener();
+    /// scanner.insert_known("bf0123456789abcdef", "192.168.1.40", Version::V3_4);
+    ///
+    /// let found = scanner
+    ///     .discover_device_instance("bf0123456789abcdef")
+    ///     .await
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!(found.ip, "192.168.1.40");
+    /// assert_eq!(found.version, Some(Version::V3_4));
+    /// assert_eq!(found.source, DiscoverySource::Manual);
+    /// # });
+    /// ```
+    pub fn insert_known(&self, id: impl Into<String>, ip: impl Into<String>, version: Version) {
+        let id = id.into();
+        let ip = ip.into();
+        let version = (version != Version::Auto).then_some(version);
+        let mut raw = serde_json::json!({ "gwId": id, "ip": ip });
+        if let Some(v) = version {
+            raw["version"] = Value::String(v.to_string());
+        }
+
+        let res = DiscoveryResult {
+            id: id.clone(),
+            ip,
+            observed_addr: None,
+            version,
+            port: None,
+            product_key: None,
+            active: None,
+            ability: None,
+            encrypt: None,
+            token: None,
+            wf_cfg: None,
+            raw,
+            source: DiscoverySource::Manual,
+            discovered_at_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            discovered_at: Instant::now(),
+        };
+
+        let state = &self.inner;
+        let mut guard = state.cache.write();
+        guard.insert(id, res);
+        state.prune_cache(&mut guard);
+        state.notify.notify_waiters();
+    }
+
     /// Invalidates the cache entry for a specific device.
     #[must_use]
     pub fn invalidate_cache(&self, id: &str) -> bool {
@@ -827,8 +1364,30 @@ impl Scanner {
             Some(DiscoveryResult {
                 id: id.to_string(),
                 ip: ip.to_string(),
+                observed_addr: None,
                 version: ver_s.and_then(|s| Version::from_str(s).ok()),
+                port: val
+                    .get("port")
+                    .and_then(|v| {
+                        v.as_u64()
+                            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+                    })
+                    .and_then(|p| u16::try_from(p).ok()),
                 product_key: pk.map(std::string::ToString::to_string),
+                active: val.get("active").and_then(Value::as_u64),
+                ability: val
+                    .get("ablilty")
+                    .or_else(|| val.get("ability"))
+                    .and_then(Value::as_u64),
+                encrypt: val.get("encrypt").and_then(Value::as_bool),
+                token: val.get("token").and_then(Value::as_bool),
+                wf_cfg: val.get("wf_cfg").and_then(Value::as_bool),
+                raw: val.clone(),
+                source: DiscoverySource::Passive,
+                discovered_at_unix: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
                 discovered_at: Instant::now(),
             })
         } else {
@@ -843,6 +1402,18 @@ pub struct ScannerBuilder {
     timeout: Option<Duration>,
     bind_addr: Option<String>,
     ports: Option<Vec<u16>>,
+    broadcast_interval: Option<Duration>,
+    broadcast_jitter: Option<Duration>,
+    max_broadcasts: Option<Option<u32>>,
+    all_interfaces: bool,
+    interface: Option<String>,
+    use_source_ip: bool,
+    cache_ttl: Option<Duration>,
+    cache_max_entries: Option<usize>,
+    scan_cooldown: Option<Duration>,
+    udp_keys: Vec<Vec<u8>>,
+    broadcast_payload: Option<BroadcastPayload>,
+    no_passive_listener: bool,
 }
 
 impl ScannerBuilder {
@@ -869,13 +1440,113 @@ impl ScannerBuilder {
         self
     }
 
+    /// Sets the interval between discovery broadcasts.
+    pub fn broadcast_interval(mut self, interval: Duration) -> Self {
+        self.broadcast_interval = Some(interval);
+        self
+    }
+
+    /// Sets the maximum random delay added before each broadcast.
+    pub fn broadcast_jitter(mut self, jitter: Duration) -> Self {
+        self.broadcast_jitter = Some(jitter);
+        self
+    }
+
+    /// Sets the maximum number of broadcasts per scan (`None` to rebroadcast until the timeout).
+    pub fn max_broadcasts(mut self, max: Option<u32>) -> Self {
+        self.max_broadcasts = Some(max);
+        self
+    }
+
+    /// Broadcasts on every local IPv4 interface instead of only the default route.
+    pub fn all_interfaces(mut self, all: bool) -> Self {
+        self.all_interfaces = all;
+        self
+    }
+
+    /// Adds vendor-specific UDP keys, tried after the built-in ones when decoding announcements.
+    pub fn udp_keys(mut self, keys: Vec<Vec<u8>>) -> Self {
+        self.udp_keys = keys;
+        self
+    }
+
+    /// Overrides the discovery broadcast payload per port. See
+    /// [`Scanner::set_broadcast_payload`].
+    pub fn broadcast_payload<F>(mut self, payload: F) -> Self
+    where
+        F: Fn(u16) -> Option<(Value, u32)> + Send + Sync + 'static,
+    {
+        self.broadcast_payload = Some(BroadcastPayload(Arc::new(payload)));
+        self
+    }
+
+    /// Broadcasts only on the named network interface (e.g. `"eth1"`).
+    pub fn interface<S: Into<String>>(mut self, name: S) -> Self {
+        self.interface = Some(name.into());
+        self
+    }
+
+    /// Reports the UDP source address as `ip` when it differs from the device's payload.
+    pub fn use_source_ip(mut self, enable: bool) -> Self {
+        self.use_source_ip = enable;
+        self
+    }
+
+    /// Sets how long a discovered device stays in the cache without being seen again.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets how long cached results are trusted and new scans are held back.
+    /// See [`Scanner::set_scan_cooldown`].
+    pub fn scan_cooldown(mut self, cooldown: Duration) -> Self {
+        self.scan_cooldown = Some(cooldown);
+        self
+    }
+
+    /// Caps the number of cached devices, evicting the least recently seen ones first.
+    pub fn cache_max_entries(mut self, max: usize) -> Self {
+        self.cache_max_entries = Some(max);
+        self
+    }
+
+    /// Keeps UDP ports bound in the background for passive discovery (default: true).
+    /// When disabled, ports are bound only while an active scan is running.
+    pub fn passive_listener(mut self, enable: bool) -> Self {
+        self.no_passive_listener = !enable;
+        self
+    }
+
+    /// Same as [`passive_listener`](Self::passive_listener).
+    pub fn passive(self, enable: bool) -> Self {
+        self.passive_listener(enable)
+    }
+
     /// Builds and returns a new `Scanner`.
     pub fn build(self) -> Scanner {
+        let state = ScannerState::new();
+        if let Some(ttl) = self.cache_ttl {
+            *state.cache_ttl.write() = ttl;
+        }
+        *state.cache_max_entries.write() = self.cache_max_entries;
+        if let Some(cooldown) = self.scan_cooldown {
+            *state.scan_cooldown.write() = cooldown;
+        }
+        *state.udp_keys.write() = self.udp_keys;
+        *state.broadcast_payload.write() = self.broadcast_payload;
         let scanner = Scanner {
-            inner: Arc::new(ScannerState::new()),
+            inner: Arc::new(state),
             timeout: self.timeout.unwrap_or(DEFAULT_SCAN_TIMEOUT),
             bind_addr: self.bind_addr.unwrap_or_else(|| "0.0.0.0".to_string()),
             ports: self.ports.unwrap_or_else(|| vec![6666, 6667, 7000]),
+            broadcast_interval: self.broadcast_interval.unwrap_or(BROADCAST_INTERVAL),
+            broadcast_jitter: self.broadcast_jitter.unwrap_or(BROADCAST_JITTER),
+            max_broadcasts: self.max_broadcasts.unwrap_or(Some(MAX_BROADCASTS)),
+            all_interfaces: self.all_interfaces,
+            interface: self.interface,
+            use_source_ip: self.use_source_ip,
+            passive_listener: !self.no_passive_listener,
         };
         scanner.ensure_passive_listener();
         scanner
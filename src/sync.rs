@@ -3,9 +3,14 @@
 //! Provides blocking handles for devices, managers, and scanners by bridging to the async core.
 //! This allows using the library in non-async environments without manually managing a runtime.
 
+#[cfg(feature = "serde")]
+use crate::config::DeviceConfig;
 use crate::device::SubDevice as AsyncSubDevice;
 use crate::device::{
-    Device as AsyncDevice, DeviceBuilder as AsyncDeviceBuilder, DeviceEvent,
+    ConnectionEvent, Device as AsyncDevice, DeviceBuilder as AsyncDeviceBuilder, DeviceEvent,
+    OutboundPacket, ProxyConfig, StatusReport, SubDeviceStatus, WifiInfo,
+    connection_changes as async_connection_changes,
+    discover_all_sub_devices as async_discover_all_sub_devices, dps_object, lagged_message,
     unified_listener as async_unified_listener,
 };
 use crate::error::Result;
@@ -14,9 +19,11 @@ use crate::runtime::{self, get_runtime};
 use crate::scanner::{DiscoveryResult, Scanner as AsyncScanner, get as get_async_scanner};
 use serde::Serialize;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::OnceLock;
 use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
 
 /// Default capacity for synchronous event channels to prevent memory buildup.
@@ -42,6 +49,21 @@ fn send_sync<C, R>(tx: &mpsc::Sender<SyncRequest<C, R>>, command: C) -> Result<R
         .map_err(|_| crate::error::TuyaError::Io("Worker died".into()))?
 }
 
+/// Runs a one-shot future on the background runtime and blocks until it completes.
+fn run_blocking<T, F>(future: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: std::future::Future<Output = T> + Send + 'static,
+{
+    let (resp_tx, resp_rx) = std::sync::mpsc::channel();
+    runtime::spawn(async move {
+        let _ = resp_tx.send(future.await);
+    });
+    resp_rx
+        .recv()
+        .map_err(|_| crate::error::TuyaError::Io("Worker died".into()))
+}
+
 macro_rules! wait_for_response {
     ($tx:expr, $cmd_gen:expr) => {{
         let (resp_tx, resp_rx) = std::sync::mpsc::channel();
@@ -77,6 +99,8 @@ pub struct Device {
 
 impl Device {
     /// Creates a new device with default settings and starts the connection task.
+    /// Reuses the connection of a running device with the same ID and key, like the async
+    /// `Device::new`.
     pub fn new<I, K>(id: I, local_key: K) -> Self
     where
         I: Into<String>,
@@ -94,6 +118,21 @@ impl Device {
         DeviceBuilder::new(id, local_key)
     }
 
+    /// Discovers the device on the local network and returns it once connected.
+    pub fn connect<I, K>(id: I, local_key: K) -> Result<Self>
+    where
+        I: Into<String>,
+        K: Into<Vec<u8>>,
+    {
+        DeviceBuilder::new(id, local_key).connect()
+    }
+
+    /// Creates a device from inventory settings and starts the connection task.
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: &DeviceConfig) -> Self {
+        Self::from_async(config.run())
+    }
+
     pub(crate) fn from_async(device: AsyncDevice) -> Self {
         let (tx, mut rx) = mpsc::channel::<SyncRequest<DeviceCommand>>(32);
         let inner_clone = device.clone();
@@ -139,10 +178,25 @@ impl Device {
         send_sync(&self.cmd_tx, DeviceCommand::Status)
     }
 
+    pub fn status_request(&self) -> Result<Value> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.status_request().await })?
+    }
+
     pub fn set_dps(&self, dps: Value) -> Result<Option<String>> {
         send_sync(&self.cmd_tx, DeviceCommand::SetDps(dps))
     }
 
+    /// Sets several DPs in one command from `(dp_id, value)` pairs. See the async
+    /// `set_multiple`.
+    pub fn set_multiple<I, K>(&self, dps: I) -> Result<Option<String>>
+    where
+        I: IntoIterator<Item = (K, Value)>,
+        K: ToString,
+    {
+        self.set_dps(dps_object(dps))
+    }
+
     pub fn set_value<I: ToString, T: Serialize>(
         &self,
         dp_id: I,
@@ -158,6 +212,22 @@ impl Device {
         }
     }
 
+    /// Sends several requests back-to-back and collects the replies. See the async `pipeline`.
+    pub fn pipeline(
+        &self,
+        requests: Vec<(crate::protocol::CommandType, Option<Value>, Option<String>)>,
+    ) -> Vec<Result<TuyaMessage>> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.pipeline(requests).await }).unwrap_or_else(|e| vec![Err(e)])
+    }
+
+    /// Sets DP values one at a time, in order. See the async `set_sequence`.
+    pub fn set_sequence(&self, steps: Vec<(u32, Value)>) -> Vec<Result<Option<String>>> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.set_sequence(steps).await })
+            .unwrap_or_else(|e| vec![Err(e)])
+    }
+
     pub fn request(
         &self,
         cmd: crate::protocol::CommandType,
@@ -174,14 +244,96 @@ impl Device {
         )
     }
 
+    pub fn execute_scene(&self, scene_id: &str) -> Result<Option<String>> {
+        let inner = self.inner.clone();
+        let scene_id = scene_id.to_string();
+        run_blocking(async move { inner.execute_scene(&scene_id).await })?
+    }
+
     pub fn sub_discover(&self) -> Result<Option<String>> {
         send_sync(&self.cmd_tx, DeviceCommand::SubDiscover)
     }
 
+    /// Like `sub_discover`, but resends the query when no report arrives in time.
+    pub fn sub_discover_with_retry(
+        &self,
+        retries: u32,
+        per_attempt_timeout: Duration,
+    ) -> Result<Option<String>> {
+        let inner = self.inner.clone();
+        run_blocking(async move {
+            inner
+                .sub_discover_with_retry(retries, per_attempt_timeout)
+                .await
+        })?
+    }
+
+    /// Queries the gateway for its sub-devices and their online state.
+    pub fn sub_devices(&self) -> Result<Vec<SubDeviceStatus>> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.sub_devices().await })?
+    }
+
+    /// Queries the status and merges every DP report received within `window`.
+    pub fn status_collect(&self, window: Duration) -> Result<Value> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.status_collect(window).await })?
+    }
+
+    /// Measures the device's response time. See the async `ping`.
+    pub fn ping(&self) -> Result<Duration> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.ping().await })?
+    }
+
+    /// Blocks until a message satisfying `pred` arrives. See the async `receive_where`.
+    pub fn receive_where<F>(&self, pred: F, wait: Duration) -> Result<TuyaMessage>
+    where
+        F: Fn(&TuyaMessage) -> bool + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.receive_where(pred, wait).await })?
+    }
+
+    /// Asks the device for its Wi-Fi SSID and signal strength.
+    pub fn query_wifi(&self) -> Result<WifiInfo> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.query_wifi().await })?
+    }
+
+    /// Asks the device for its DP schema. See the async `query_schema`.
+    pub fn query_schema(&self) -> Result<Value> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.query_schema().await })?
+    }
+
     pub fn sub(&self, cid: &str) -> SubDevice {
         SubDevice::new(self.inner.sub(cid))
     }
 
+    /// Blocks until the device is connected or `timeout` elapses.
+    pub fn wait_connected(&self, timeout: Duration) -> Result<()> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.wait_connected(timeout).await })?
+    }
+
+    /// Finds the protocol version the device speaks. See the async `probe_version`.
+    pub fn probe_version(&self) -> Result<Version> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.probe_version().await })?
+    }
+
+    pub fn reconnect_now(&self) {
+        let inner = self.inner.clone();
+        let _ = run_blocking(async move { inner.reconnect_now().await });
+    }
+
+    pub fn set_local_key<K: Into<Vec<u8>>>(&self, local_key: K) {
+        let inner = self.inner.clone();
+        let local_key = local_key.into();
+        let _ = run_blocking(async move { inner.set_local_key(local_key).await });
+    }
+
     pub fn close(&self) {
         let _ = send_sync(&self.cmd_tx, DeviceCommand::Close);
     }
@@ -190,14 +342,114 @@ impl Device {
         let _ = send_sync(&self.cmd_tx, DeviceCommand::Stop);
     }
 
+    /// Stops the device once already queued commands are answered or `timeout` elapses.
+    pub fn stop_graceful(&self, timeout: Duration) {
+        let inner = self.inner.clone();
+        let _ = run_blocking(async move { inner.stop_graceful(timeout).await });
+    }
+
     pub fn listener(&self) -> std::sync::mpsc::Receiver<TuyaMessage> {
         let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
-        let mut broadcast_rx = self.inner.broadcast_tx.subscribe();
+        let (mut broadcast_rx, replay) = self.inner.subscribe_with_replay();
+        let prefix = self.inner.event_prefix();
+
+        runtime::spawn(async move {
+            for msg in replay {
+                if tx.try_send(msg).is_err() {
+                    return;
+                }
+            }
+            loop {
+                match broadcast_rx.recv().await {
+                    Ok(msg) => {
+                        if !msg.payload.is_empty() && tx.try_send(msg).is_err() {
+                            // Buffer full or receiver dropped
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        if tx.try_send(lagged_message(n, prefix)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Returns a receiver for this device's errors only. See the async `errors`.
+    pub fn errors(&self) -> std::sync::mpsc::Receiver<crate::error::TuyaError> {
+        use futures_util::StreamExt;
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
+        let stream = self.inner.errors();
 
         runtime::spawn(async move {
-            while let Ok(msg) = broadcast_rx.recv().await {
-                if !msg.payload.is_empty() && tx.try_send(msg).is_err() {
-                    // Buffer full or receiver dropped
+            futures_util::pin_mut!(stream);
+            while let Some(err) = stream.next().await {
+                if tx.try_send(err).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Returns a receiver for connection attempts and backoff waits. See the async
+    /// `connection_events`.
+    pub fn connection_events(&self) -> std::sync::mpsc::Receiver<ConnectionEvent> {
+        use futures_util::StreamExt;
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
+        let stream = self.inner.connection_events();
+
+        runtime::spawn(async move {
+            futures_util::pin_mut!(stream);
+            while let Some(event) = stream.next().await {
+                if tx.try_send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Returns a receiver for status reports the device pushes on its own. See the async
+    /// `status_reports`.
+    pub fn status_reports(&self) -> std::sync::mpsc::Receiver<StatusReport> {
+        use futures_util::StreamExt;
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
+        let stream = self.inner.status_reports();
+
+        runtime::spawn(async move {
+            futures_util::pin_mut!(stream);
+            while let Some(report) = stream.next().await {
+                if tx.try_send(report).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Returns a receiver for the packets written to the device. See the async `outbound_packets`.
+    pub fn outbound_packets(&self) -> std::sync::mpsc::Receiver<OutboundPacket> {
+        use futures_util::StreamExt;
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
+        let stream = self.inner.outbound_packets();
+
+        runtime::spawn(async move {
+            futures_util::pin_mut!(stream);
+            while let Some(packet) = stream.next().await {
+                if tx.try_send(packet).is_err() {
                     break;
                 }
             }
@@ -261,14 +513,154 @@ impl DeviceBuilder {
         self
     }
 
+    /// Same semantics as the async builder: command methods return `Ok(None)` once the
+    /// command is queued instead of blocking until the reply arrives.
     pub fn nowait(mut self, nowait: bool) -> Self {
         self.inner = self.inner.nowait(nowait);
         self
     }
 
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.inner = self.inner.tcp_nodelay(nodelay);
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.inner = self.inner.tcp_keepalive(keepalive);
+        self
+    }
+
+    pub fn min_command_interval(mut self, interval: Duration) -> Self {
+        self.inner = self.inner.min_command_interval(interval);
+        self
+    }
+
+    pub fn handshake_timeout(mut self, budget: Duration) -> Self {
+        self.inner = self.inner.handshake_timeout(budget);
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.inner = self.inner.request_timeout(timeout);
+        self
+    }
+
+    pub fn status_on_connect(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.status_on_connect(enabled);
+        self
+    }
+
+    pub fn refresh_dps_on_status(mut self, dps: Vec<u32>) -> Self {
+        self.inner = self.inner.refresh_dps_on_status(dps);
+        self
+    }
+
+    pub fn resync_frames(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.resync_frames(enabled);
+        self
+    }
+
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.inner = self.inner.max_frame_size(size);
+        self
+    }
+
+    pub fn max_prefix_scan(mut self, max_skip: usize) -> Self {
+        self.inner = self.inner.max_prefix_scan(max_skip);
+        self
+    }
+
+    pub fn legacy_query(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.legacy_query(enabled);
+        self
+    }
+
+    pub fn control_base64(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.control_base64(enabled);
+        self
+    }
+
+    pub fn verify_checksum(mut self, verify: bool) -> Self {
+        self.inner = self.inner.verify_checksum(verify);
+        self
+    }
+
+    pub fn auto_device22(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.auto_device22(enabled);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.inner = self.inner.proxy(proxy);
+        self
+    }
+
+    pub fn event_buffer(mut self, capacity: usize) -> Self {
+        self.inner = self.inner.event_buffer(capacity);
+        self
+    }
+
+    pub fn replay_buffer(mut self, n: usize) -> Self {
+        self.inner = self.inner.replay_buffer(n);
+        self
+    }
+
+    pub fn dry_run(mut self, enable: bool) -> Self {
+        self.inner = self.inner.dry_run(enable);
+        self
+    }
+
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.inner = self.inner.shared(shared);
+        self
+    }
+
+    pub fn clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.inner = self.inner.clock(clock);
+        self
+    }
+
+    /// Registers a callback run after every successful connection. It receives the
+    /// underlying async device (also reachable as `Device::inner`).
+    pub fn on_connect<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&AsyncDevice) + Send + Sync + 'static,
+    {
+        self.inner = self.inner.on_connect(hook);
+        self
+    }
+
+    /// Registers a callback run when an established connection is lost.
+    pub fn on_disconnect<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&AsyncDevice, &crate::error::TuyaError) + Send + Sync + 'static,
+    {
+        self.inner = self.inner.on_disconnect(hook);
+        self
+    }
+
+    /// Registers a callback that sees every raw frame sent to and read from the device.
+    pub fn wire_tap<F>(mut self, tap: F) -> Self
+    where
+        F: Fn(crate::device::Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.inner = self.inner.wire_tap(tap);
+        self
+    }
+
+    /// Starts the device. A running device with the same ID and key is reused unless
+    /// `shared(false)` is set; see the async `DeviceBuilder::run`.
     pub fn run(self) -> Device {
         Device::from_async(self.inner.run())
     }
+
+    /// Discovers the device and blocks until the first handshake succeeds or fails.
+    pub fn connect(self) -> Result<Device> {
+        run_blocking(self.inner.connect())?.map(Device::from_async)
+    }
 }
 
 // --- SubDevice ---
@@ -322,6 +714,18 @@ impl SubDevice {
         send_sync(&self.cmd_tx, SubDeviceCommand::Status)
     }
 
+    /// Asks the gateway to re-report this sub-device's DPs.
+    pub fn refresh(&self) -> Result<Option<String>> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.refresh().await })?
+    }
+
+    /// Returns this sub-device's current DPs. See the async `status_now`.
+    pub fn status_now(&self) -> Result<Value> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.status_now().await })?
+    }
+
     pub fn set_dps(&self, dps: Value) -> Result<Option<String>> {
         send_sync(&self.cmd_tx, SubDeviceCommand::SetDps(dps))
     }
@@ -341,6 +745,15 @@ impl SubDevice {
         }
     }
 
+    /// Sets several DPs in one command. See the async `set_values`.
+    pub fn set_values(&self, values: Vec<(u32, Value)>) -> Result<Option<String>> {
+        let dps = values
+            .into_iter()
+            .map(|(dp, value)| (dp.to_string(), value))
+            .collect();
+        self.set_dps(Value::Object(dps))
+    }
+
     pub fn request(
         &self,
         cmd: crate::protocol::CommandType,
@@ -351,6 +764,16 @@ impl SubDevice {
             SubDeviceCommand::Request { command: cmd, data },
         )
     }
+
+    /// Sends a command and blocks until the gateway's reply for this sub-device arrives.
+    pub fn request_await(
+        &self,
+        cmd: crate::protocol::CommandType,
+        data: Option<Value>,
+    ) -> Result<TuyaMessage> {
+        let inner = self.inner.clone();
+        run_blocking(async move { inner.request_await(cmd, data).await })?
+    }
 }
 
 impl Deref for SubDevice {
@@ -363,8 +786,15 @@ impl Deref for SubDevice {
 // --- Scanner ---
 
 enum ScannerCommand {
-    Scan(std::sync::mpsc::Sender<Result<Vec<DiscoveryResult>>>),
-    Discover(String, std::sync::mpsc::Sender<Option<DiscoveryResult>>),
+    Scan(
+        Option<Duration>,
+        std::sync::mpsc::Sender<Result<Vec<DiscoveryResult>>>,
+    ),
+    Discover(
+        String,
+        Option<Duration>,
+        std::sync::mpsc::Sender<Result<Option<DiscoveryResult>>>,
+    ),
 }
 
 #[derive(Clone)]
@@ -390,6 +820,11 @@ impl Scanner {
         Self::from_async(get_async_scanner().clone())
     }
 
+    /// Creates a scanner with its own cache and scan state, independent of `get()`.
+    pub fn new_isolated() -> Self {
+        Self::from_async(AsyncScanner::new_isolated())
+    }
+
     pub(crate) fn from_async(async_scanner: AsyncScanner) -> Self {
         let (tx, mut rx) = mpsc::channel::<ScannerCommand>(32);
         let scanner_inner = async_scanner.clone();
@@ -400,16 +835,18 @@ impl Scanner {
         runtime::spawn(async move {
             while let Some(cmd) = rx.recv().await {
                 match cmd {
-                    ScannerCommand::Scan(resp_tx) => {
-                        let res = scanner_inner.scan_instance().await;
+                    ScannerCommand::Scan(timeout, resp_tx) => {
+                        let res = match timeout {
+                            Some(t) => scanner_inner.scan_with_timeout(t).await,
+                            None => scanner_inner.scan_instance().await,
+                        };
                         let _ = resp_tx.send(res);
                     }
-                    ScannerCommand::Discover(id, resp_tx) => {
-                        let res = scanner_inner
-                            .discover_device_instance(&id)
-                            .await
-                            .ok()
-                            .flatten();
+                    ScannerCommand::Discover(id, timeout, resp_tx) => {
+                        let res = match timeout {
+                            Some(t) => scanner_inner.discover_device_with_timeout(&id, t).await,
+                            None => scanner_inner.discover_device_instance(&id).await,
+                        };
                         let _ = resp_tx.send(res);
                     }
                 }
@@ -429,7 +866,27 @@ impl Scanner {
 
     /// Instance version of `scan`.
     pub fn scan_instance(&self) -> Result<Vec<DiscoveryResult>> {
-        wait_for_response!(self.cmd_tx, ScannerCommand::Scan)?
+        wait_for_response!(self.cmd_tx, |resp_tx| ScannerCommand::Scan(None, resp_tx))?
+    }
+
+    /// Scans with a timeout for this call only, leaving the scanner's timeout untouched.
+    pub fn scan_with_timeout(&self, timeout: Duration) -> Result<Vec<DiscoveryResult>> {
+        wait_for_response!(self.cmd_tx, |resp_tx| ScannerCommand::Scan(
+            Some(timeout),
+            resp_tx
+        ))?
+    }
+
+    /// Scans and returns only devices that look unpaired.
+    pub fn discover_unpaired() -> Result<Vec<DiscoveryResult>> {
+        Self::get().discover_unpaired_instance()
+    }
+
+    /// Instance version of `discover_unpaired`.
+    pub fn discover_unpaired_instance(&self) -> Result<Vec<DiscoveryResult>> {
+        let mut results = self.scan_instance()?;
+        results.retain(DiscoveryResult::is_unpaired);
+        Ok(results)
     }
 
     /// Discovers a specific device by ID.
@@ -441,12 +898,58 @@ impl Scanner {
     pub fn discover_instance(&self, id: &str) -> Option<DiscoveryResult> {
         wait_for_response!(self.cmd_tx, |resp_tx| ScannerCommand::Discover(
             id.to_string(),
+            None,
             resp_tx
         ))
         .ok()
+        .and_then(Result::ok)
         .flatten()
     }
 
+    /// Discovers a specific device with a timeout for this call only. Scan failures (e.g.
+    /// the discovery ports cannot be bound) are returned as `Err`; `Ok(None)` means the
+    /// device did not answer within `timeout`. See the async `discover_device_with_timeout`.
+    pub fn discover_device_with_timeout(
+        &self,
+        id: &str,
+        timeout: Duration,
+    ) -> Result<Option<DiscoveryResult>> {
+        wait_for_response!(self.cmd_tx, |resp_tx| ScannerCommand::Discover(
+            id.to_string(),
+            Some(timeout),
+            resp_tx
+        ))?
+    }
+
+    /// Sets how long a discovered device stays in the cache without being seen again.
+    pub fn set_cache_ttl(&self, ttl: Duration) {
+        self.inner.set_cache_ttl(ttl);
+    }
+
+    /// Caps the number of cached devices (`None` for unbounded).
+    pub fn set_cache_max_entries(&self, max: Option<usize>) {
+        self.inner.set_cache_max_entries(max);
+    }
+
+    /// Sets how long cached results are trusted and new scans are held back.
+    pub fn set_scan_cooldown(&self, cooldown: Duration) {
+        self.inner.set_scan_cooldown(cooldown);
+    }
+
+    /// Seeds the discovery cache with a device whose address is already known.
+    pub fn insert_known(&self, id: &str, ip: &str, version: Version) {
+        self.inner.insert_known(id, ip, version);
+    }
+
+    /// Overrides the discovery broadcast payload per port. See the async
+    /// `set_broadcast_payload`.
+    pub fn set_broadcast_payload<F>(&self, payload: F)
+    where
+        F: Fn(u16) -> Option<(Value, u32)> + Send + Sync + 'static,
+    {
+        self.inner.set_broadcast_payload(payload);
+    }
+
     /// Returns a synchronous iterator (Receiver) that yields discovery results in real-time.
     pub fn scan_stream() -> std::sync::mpsc::Receiver<DiscoveryResult> {
         Self::get().scan_stream_instance()
@@ -505,11 +1008,105 @@ impl ScannerBuilder {
         self
     }
 
+    pub fn broadcast_interval(mut self, interval: Duration) -> Self {
+        self.inner = self.inner.broadcast_interval(interval);
+        self
+    }
+
+    pub fn broadcast_jitter(mut self, jitter: Duration) -> Self {
+        self.inner = self.inner.broadcast_jitter(jitter);
+        self
+    }
+
+    pub fn max_broadcasts(mut self, max: Option<u32>) -> Self {
+        self.inner = self.inner.max_broadcasts(max);
+        self
+    }
+
+    pub fn all_interfaces(mut self, all: bool) -> Self {
+        self.inner = self.inner.all_interfaces(all);
+        self
+    }
+
+    pub fn broadcast_payload<F>(mut self, payload: F) -> Self
+    where
+        F: Fn(u16) -> Option<(Value, u32)> + Send + Sync + 'static,
+    {
+        self.inner = self.inner.broadcast_payload(payload);
+        self
+    }
+
+    pub fn udp_keys(mut self, keys: Vec<Vec<u8>>) -> Self {
+        self.inner = self.inner.udp_keys(keys);
+        self
+    }
+
+    pub fn use_source_ip(mut self, enable: bool) -> Self {
+        self.inner = self.inner.use_source_ip(enable);
+        self
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.inner = self.inner.cache_ttl(ttl);
+        self
+    }
+
+    pub fn cache_max_entries(mut self, max: usize) -> Self {
+        self.inner = self.inner.cache_max_entries(max);
+        self
+    }
+
+    pub fn scan_cooldown(mut self, cooldown: Duration) -> Self {
+        self.inner = self.inner.scan_cooldown(cooldown);
+        self
+    }
+
+    pub fn passive_listener(mut self, enable: bool) -> Self {
+        self.inner = self.inner.passive_listener(enable);
+        self
+    }
+
+    pub fn passive(mut self, enable: bool) -> Self {
+        self.inner = self.inner.passive(enable);
+        self
+    }
+
+    pub fn interface<S: Into<String>>(mut self, name: S) -> Self {
+        self.inner = self.inner.interface(name);
+        self
+    }
+
     pub fn build(self) -> Scanner {
         Scanner::from_async(self.inner.build())
     }
 }
 
+/// Receives `(device_id, connected)` whenever one of the devices connects or disconnects.
+/// See the async `connection_changes`.
+pub fn connection_changes(devices: Vec<Device>) -> std::sync::mpsc::Receiver<(String, bool)> {
+    let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);
+    let async_devices: Vec<AsyncDevice> = devices.into_iter().map(|d| d.inner.clone()).collect();
+
+    runtime::spawn(async move {
+        use futures_util::StreamExt;
+        let mut stream = async_connection_changes(async_devices);
+        while let Some(change) = stream.next().await {
+            if tx.try_send(change).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Queries every device for its sub-devices concurrently, keyed by gateway ID. Devices
+/// that fail the query are left out. See the async `discover_all_sub_devices`.
+pub fn discover_all_sub_devices(devices: Vec<Device>) -> HashMap<String, Vec<SubDeviceStatus>> {
+    let async_devices: Vec<AsyncDevice> = devices.into_iter().map(|d| d.inner.clone()).collect();
+    run_blocking(async_discover_all_sub_devices(async_devices)).unwrap_or_default()
+}
+
 /// Merges multiple sync device listeners into a single synchronous receiver.
 pub fn unified_listener(devices: Vec<Device>) -> std::sync::mpsc::Receiver<Result<DeviceEvent>> {
     let (tx, rx) = std::sync::mpsc::sync_channel(CHAN_SYNC_CAPACITY);